@@ -0,0 +1,81 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::models::user::UserRole;
+
+// What a partner integration may do with an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "bookings:read")]
+    BookingsRead,
+    #[serde(rename = "bookings:write")]
+    BookingsWrite,
+    #[serde(rename = "services:read")]
+    ServicesRead,
+}
+
+impl ApiScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiScope::BookingsRead => "bookings:read",
+            ApiScope::BookingsWrite => "bookings:write",
+            ApiScope::ServicesRead => "services:read",
+        }
+    }
+
+    // Whether keys of users with `role` may hold the scope, only professionals and admins act
+    // on bookings for partners
+    pub fn allowed_for(self, role: UserRole) -> bool {
+        match self {
+            ApiScope::BookingsWrite => matches!(role, UserRole::Professional | UserRole::Admin),
+            ApiScope::BookingsRead | ApiScope::ServicesRead => true,
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::api_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiKey {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::api_keys)]
+pub struct NewApiKey {
+    pub user_id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    #[validate(length(min = 1, message = "at least one scope is required"))]
+    pub scopes: Vec<ApiScope>,
+    #[validate(custom(function = "validate_expiry"))]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn validate_expiry(expires_at: &DateTime<Utc>) -> Result<(), ValidationError> {
+    if *expires_at <= Utc::now() {
+        return Err(ValidationError::new("expires_at").with_message("must be in the future".into()));
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+
+// Kinds of events in the audit log, stored by their snake_case name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    Registered,
+    LoginSucceeded,
+    LoginFailed,
+    AccountLocked,
+    MfaFailed,
+    Logout,
+    LogoutEverywhere,
+    SessionRevoked,
+    PasswordResetRequested,
+    PasswordReset,
+    PasswordChanged,
+    EmailChanged,
+    PhoneChanged,
+    ProfileUpdated,
+    MfaEnabled,
+    MfaDisabled,
+    ApiKeyCreated,
+    ApiKeyRevoked,
+    AccountDeletionRequested,
+}
+
+impl AuditEventType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditEventType::Registered => "registered",
+            AuditEventType::LoginSucceeded => "login_succeeded",
+            AuditEventType::LoginFailed => "login_failed",
+            AuditEventType::AccountLocked => "account_locked",
+            AuditEventType::MfaFailed => "mfa_failed",
+            AuditEventType::Logout => "logout",
+            AuditEventType::LogoutEverywhere => "logout_everywhere",
+            AuditEventType::SessionRevoked => "session_revoked",
+            AuditEventType::PasswordResetRequested => "password_reset_requested",
+            AuditEventType::PasswordReset => "password_reset",
+            AuditEventType::PasswordChanged => "password_changed",
+            AuditEventType::EmailChanged => "email_changed",
+            AuditEventType::PhoneChanged => "phone_changed",
+            AuditEventType::ProfileUpdated => "profile_updated",
+            AuditEventType::MfaEnabled => "mfa_enabled",
+            AuditEventType::MfaDisabled => "mfa_disabled",
+            AuditEventType::ApiKeyCreated => "api_key_created",
+            AuditEventType::ApiKeyRevoked => "api_key_revoked",
+            AuditEventType::AccountDeletionRequested => "account_deletion_requested",
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::audit_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct AuditEvent {
+    pub id: i64,
+    pub event_type: String,
+    pub actor_id: Option<i32>,
+    pub subject_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub metadata: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::audit_events)]
+pub struct NewAuditEvent<'a> {
+    pub event_type: &'a str,
+    pub actor_id: Option<i32>,
+    pub subject_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<&'a str>,
+    pub metadata: serde_json::Value,
+}
+
+// Filters of the admin audit log query, newest events first
+#[derive(Debug, Deserialize)]
+pub struct AuditEventQuery {
+    // Events the user did or that were done to them
+    pub user_id: Option<i32>,
+    pub event_type: Option<AuditEventType>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    // Only events with a smaller id, the last id of the previous page
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
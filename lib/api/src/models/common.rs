@@ -13,4 +13,51 @@ pub struct FieldSelection {
     pub order: Option<String>,
     #[serde(rename = "skip")]
     pub off_set: Option<i32>,
+    // Bounds of `created_at`, inclusive and exclusive
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+    // Comma separated values that must all be in `professional_info`
+    pub skills: Option<String>,
+    pub languages: Option<String>,
+    pub certifications: Option<String>,
+    // Bounds of `professional_info.years_of_experience`, both inclusive
+    pub min_experience: Option<String>,
+    pub max_experience: Option<String>,
+    // Comma separated `professional_info` keys that must be present, e.g. "certifications"
+    pub has_info: Option<String>,
+    // Token of the `next` or `prev` page of an earlier response, instead of `skip`
+    pub cursor: Option<String>,
+    // Also count all the matching rows
+    pub total: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Pagination {
+    pub limit: Option<i32>,
+    #[serde(rename = "skip")]
+    pub off_set: Option<i32>,
+}
+
+#[derive(Debug, diesel::deserialize::QueryableByName)]
+pub struct RowCount {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub total: i64,
+}
+
+// A row of a list query, with the sort value and id its cursors are made of
+#[derive(Debug, diesel::deserialize::QueryableByName)]
+pub struct RawJsonItem {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub item: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub sort_key: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub row_id: i32,
+}
+
+// A service as JSON, for the partner listing
+#[derive(Debug, diesel::deserialize::QueryableByName)]
+pub struct RawJsonService {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub service: String,
 }
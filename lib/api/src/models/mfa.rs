@@ -0,0 +1,53 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+
+use crate::models::user::SessionMode;
+
+// Claims of the token handed out by `login` when a second factor is required
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MfaPendingJWT {
+    pub id: i32,
+    pub ver: i32,
+    pub jti: String,
+    // Session mode requested at login, applied once the second factor is verified
+    pub mode: SessionMode,
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::user_totp)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserTotp {
+    pub user_id: i32,
+    // Nonce followed by the AES-GCM encrypted secret
+    pub secret: Vec<u8>,
+    pub enabled_at: Option<NaiveDateTime>,
+    pub last_used_step: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::user_totp)]
+pub struct NewUserTotp {
+    pub user_id: i32,
+    pub secret: Vec<u8>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::mfa_recovery_codes)]
+pub struct NewRecoveryCode {
+    pub user_id: i32,
+    pub code_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaLoginRequest {
+    pub mfa_token: String,
+    // A TOTP code or one of the recovery codes
+    pub code: String,
+}
@@ -1,2 +1,8 @@
+pub mod api_key;
+pub mod audit;
 pub mod common;
+pub mod mfa;
+pub mod notification;
+pub mod oidc;
+pub mod session;
 pub mod user;
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+// Queue consumed by the notification worker
+pub const NOTIFICATIONS_QUEUE: &str = "notifications";
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Otp,
+    PasswordReset,
+    LoginCode,
+    ContactChange,
+    AccountDeletion,
+    Alert,
+    Marketing,
+}
+
+impl NotificationKind {
+    // Whether the text carries a credential, e.g. a reset link or a login code. It must not be
+    // stored or logged anywhere but in its hashed, short-lived record.
+    pub fn is_secret(&self) -> bool {
+        matches!(
+            self,
+            NotificationKind::Otp
+                | NotificationKind::PasswordReset
+                | NotificationKind::LoginCode
+                | NotificationKind::ContactChange
+                | NotificationKind::AccountDeletion
+        )
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NotificationMessage {
+    pub user_id: i32,
+    pub email: Option<String>,
+    pub phone_number: Option<String>,
+    pub destinations: Vec<String>, // e.g. ["email", "whatsapp"]
+    pub kind: NotificationKind,
+    pub message: Option<String>, // Optional if OTP
+}
@@ -0,0 +1,34 @@
+use chrono::NaiveDateTime;
+use diesel::Insertable;
+use serde::Deserialize;
+
+use crate::models::user::UserRole;
+
+// Query of the redirect back from an OIDC provider, `error` is set when the user cancelled
+#[derive(Debug, Deserialize)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::user_identities)]
+pub struct NewUserIdentity<'a> {
+    pub user_id: i32,
+    pub provider: &'a str,
+    pub subject: &'a str,
+    pub email: &'a str,
+}
+
+// Customer signing up through an OIDC provider, which verified the email
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::users)]
+pub struct NewOidcUser<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub role: UserRole,
+    // Hash of a random password nobody knows, a password reset sets a usable one
+    pub password: String,
+    pub email_verified_at: NaiveDateTime,
+}
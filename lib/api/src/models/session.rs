@@ -0,0 +1,29 @@
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable, Selectable};
+use serde::Serialize;
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = crate::schema::user_sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    // Refresh token family, never exposed
+    #[serde(skip)]
+    pub family_id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = crate::schema::user_sessions)]
+pub struct NewSession<'a> {
+    pub user_id: i32,
+    pub family_id: &'a str,
+    pub user_agent: Option<&'a str>,
+    pub ip_address: Option<String>,
+}
@@ -1,19 +1,36 @@
-use chrono::NaiveDateTime;
-use diesel::{Insertable, Queryable, Selectable, prelude::QueryableByName};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use collection::operations::password_policy::PasswordPolicy;
+use diesel::{AsChangeset, Insertable, Queryable, Selectable, prelude::QueryableByName};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-#[derive(Serialize, Deserialize)]
+// Access token claims
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserJWT {
     pub id: i32,
+    pub role: UserRole,
+    // `users.token_version` at the time the token was issued
+    pub ver: i32,
+    pub jti: String,
+    // Refresh token family the access token was issued from
+    pub fid: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, diesel_derive_enum::DbEnum)]
+// Refresh token claims
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshJWT {
+    pub id: i32,
+    pub ver: i32,
+    pub jti: String,
+    pub fid: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, diesel_derive_enum::DbEnum)]
 #[ExistingTypePath = "crate::schema::sql_types::UserRole"]
 #[serde(rename_all = "lowercase")]
 pub enum UserRole {
-    // Admin,
+    Admin,
     Customer,
     Professional,
 }
@@ -45,16 +62,119 @@ pub enum ServiceCategory {
     Other,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LogoutEverywhereRequest {
+    // Revoke tokens issued before this time, defaults to now
+    pub before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationChannel {
+    Email,
+    Phone,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRequest {
+    pub email: String,
+    pub channel: VerificationChannel,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyConfirmRequest {
+    pub email: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "Please enter a valid email address"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePhoneRequest {
+    pub phone_number: String,
+}
+
+// Either the current password or the code from POST /users/me/delete/code, which works for
+// accounts without a usable password
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: Option<String>,
+    pub code: Option<String>,
+}
+
+// Code sent to the new address of an email or phone change
+#[derive(Debug, Deserialize)]
+pub struct ConfirmChangeRequest {
+    pub code: String,
+}
+
+// The account is found by its email or phone number, the code is sent to the one given
+#[derive(Debug, Deserialize)]
+pub struct PasswordlessRequest {
+    pub email: Option<String>,
+    pub phone_number: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordlessLoginRequest {
+    pub email: Option<String>,
+    pub phone_number: Option<String>,
+    pub code: Option<String>,
+    // Token of the magic link, used instead of the contact and code
+    pub token: Option<String>,
+    #[serde(default)]
+    pub mode: SessionMode,
+}
+
+// How the tokens of a new session are handed to the client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionMode {
+    // HttpOnly cookies, for browsers
+    #[default]
+    Cookie,
+    // JSON body, for mobile apps and API clients sending `Authorization: Bearer`
+    Bearer,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub mode: SessionMode,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
 }
 
 #[derive(Debug, diesel::deserialize::QueryableByName)]
-pub struct RawJsonUser {
+pub struct RawJsonExport {
     #[diesel(sql_type = diesel::sql_types::Text)]
-    pub user: String,
+    pub export: String,
 }
 
 // User model
@@ -67,26 +187,54 @@ pub struct User {
     pub email: String,
     pub role: UserRole,
     pub password: String,
-    pub phone_number: String,
+    pub phone_number: Option<String>,
     pub professional_info: Option<serde_json::Value>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub token_version: i32,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub phone_verified_at: Option<NaiveDateTime>,
+    pub deletion_requested_at: Option<NaiveDateTime>,
+    pub erased_at: Option<NaiveDateTime>,
 }
 
 // Register model
 #[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Validate)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
+#[validate(context = "PasswordCheck<'v_a>")]
 pub struct RegisterUser {
     pub name: String,
     #[validate(email(message = "Please enter a valid email address"))]
     pub email: String,
+    #[validate(custom(function = "validate_registration_role"))]
     pub role: UserRole,
     pub professional_info: Option<serde_json::Value>,
+    #[validate(custom(function = "validate_password", use_context))]
     pub password: String,
     pub phone_number: String,
 }
 
+// Context of the `RegisterUser` validation, the password policy and the email and name the
+// password must not contain
+pub struct PasswordCheck<'a> {
+    pub policy: &'a PasswordPolicy,
+    pub identities: [&'a str; 2],
+}
+
+fn validate_password(password: &str, check: &PasswordCheck) -> Result<(), ValidationError> {
+    check.policy.validate(password, &check.identities)
+}
+
+// Admins are promoted by other admins, never self-registered
+fn validate_registration_role(role: &UserRole) -> Result<(), ValidationError> {
+    match role {
+        UserRole::Admin => Err(ValidationError::new("role")
+            .with_message("must be either customer or professional".into())),
+        _ => Ok(()),
+    }
+}
+
 // Insertable user (without ID)
 #[derive(Debug, Insertable)]
 #[diesel(table_name = crate::schema::users)]
@@ -97,6 +245,53 @@ pub struct NewUser<'a> {
     pub professional_info: Option<serde_json::Value>,
 }
 
+// Partial profile update, fields left out stay as they are
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateUserRequest {
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub name: Option<String>,
+    pub phone_number: Option<String>,
+    #[validate(custom(function = "validate_professional_info"))]
+    pub professional_info: Option<serde_json::Value>,
+    // Changed through their own endpoints, accepted here only to be rejected
+    #[validate(custom(function = "reject_profile_field"))]
+    pub role: Option<serde_json::Value>,
+    #[validate(custom(function = "reject_profile_field"))]
+    pub password: Option<serde_json::Value>,
+    #[validate(custom(function = "reject_email_field"))]
+    pub email: Option<serde_json::Value>,
+}
+
+fn validate_professional_info(info: &serde_json::Value) -> Result<(), ValidationError> {
+    if info.is_object() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("professional_info").with_message("must be a JSON object".into()))
+    }
+}
+
+fn reject_profile_field(_: &serde_json::Value) -> Result<(), ValidationError> {
+    Err(ValidationError::new("read_only")
+        .with_message("cannot be changed through a profile update".into()))
+}
+
+// The new address has to be confirmed with a code and the old one alerted
+fn reject_email_field(_: &serde_json::Value) -> Result<(), ValidationError> {
+    Err(ValidationError::new("read_only")
+        .with_message("is changed through POST /users/me/email".into()))
+}
+
+// Columns a profile update sets, `None` ones are left alone
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = crate::schema::users)]
+pub struct UserChanges {
+    pub name: Option<String>,
+    pub phone_number: Option<String>,
+    // A new phone number has to be verified again
+    pub phone_verified_at: Option<Option<NaiveDateTime>>,
+    pub professional_info: Option<serde_json::Value>,
+}
+
 // Service model
 #[derive(Debug, Queryable, Serialize, Insertable)]
 #[diesel(table_name = crate::schema::services)]
@@ -14,6 +14,39 @@ pub mod sql_types {
     pub struct UserRole;
 }
 
+diesel::table! {
+    api_keys (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 16]
+        prefix -> Varchar,
+        #[max_length = 255]
+        key_hash -> Varchar,
+        scopes -> Array<Text>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    audit_events (id) {
+        id -> Int8,
+        #[max_length = 64]
+        event_type -> Varchar,
+        actor_id -> Nullable<Int4>,
+        subject_id -> Nullable<Int4>,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        metadata -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::BookingStatus;
@@ -30,6 +63,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    mfa_recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 255]
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::ServiceCategory;
@@ -58,6 +102,45 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_identities (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 64]
+        provider -> Varchar,
+        #[max_length = 255]
+        subject -> Varchar,
+        #[max_length = 255]
+        email -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 64]
+        family_id -> Varchar,
+        user_agent -> Nullable<Text>,
+        #[max_length = 45]
+        ip_address -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_totp (user_id) {
+        user_id -> Int4,
+        secret -> Bytea,
+        enabled_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::UserRole;
@@ -72,15 +155,36 @@ diesel::table! {
         #[max_length = 255]
         password -> Varchar,
         #[max_length = 255]
-        phone_number -> Varchar,
+        phone_number -> Nullable<Varchar>,
         professional_info -> Nullable<Jsonb>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        token_version -> Int4,
+        email_verified_at -> Nullable<Timestamp>,
+        phone_verified_at -> Nullable<Timestamp>,
+        deletion_requested_at -> Nullable<Timestamp>,
+        erased_at -> Nullable<Timestamp>,
     }
 }
 
+diesel::joinable!(api_keys -> users (user_id));
 diesel::joinable!(bookings -> services (service_id));
+diesel::joinable!(mfa_recovery_codes -> users (user_id));
 diesel::joinable!(services -> users (professional_id));
 diesel::joinable!(transactions -> bookings (booking_id));
+diesel::joinable!(user_identities -> users (user_id));
+diesel::joinable!(user_sessions -> users (user_id));
+diesel::joinable!(user_totp -> users (user_id));
 
-diesel::allow_tables_to_appear_in_same_query!(bookings, services, transactions, users,);
+diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    audit_events,
+    bookings,
+    mfa_recovery_codes,
+    services,
+    transactions,
+    user_identities,
+    user_sessions,
+    user_totp,
+    users,
+);
@@ -0,0 +1,897 @@
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use diesel::{
+    pg::Pg,
+    query_builder::{BoxedSqlQuery, SqlQuery},
+    sql_types::{BigInt, Integer, Jsonb, Text, Timestamp},
+};
+use serde_json::json;
+
+use crate::operations::pagination::{Cursor, Direction};
+
+/// Alias of the resource table in the generated SQL.
+const TABLE_ALIAS: &str = "t";
+
+/// SQL type of a field, which decides how filter values for it are parsed and bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Integer,
+    Text,
+    Timestamp,
+    /// A Postgres enum type, given by its name and values. Values are bound as text and cast to
+    /// it.
+    Enum(&'static str, &'static [&'static str]),
+    Jsonb,
+}
+
+/// Type of a top-level key of a JSONB field, which decides how it can be filtered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    /// An array of strings, matched by containment.
+    StringArray,
+    /// A number, compared with a bound.
+    Number,
+}
+
+/// A top-level key of a JSONB field that may be filtered by.
+#[derive(Debug, Clone, Copy)]
+pub struct JsonKey {
+    pub name: &'static str,
+    pub key_type: KeyType,
+}
+
+impl JsonKey {
+    pub const fn new(name: &'static str, key_type: KeyType) -> Self {
+        Self { name, key_type }
+    }
+}
+
+/// A column a list endpoint exposes.
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub field_type: FieldType,
+    /// Whether rows can be sorted by it.
+    pub sortable: bool,
+    /// Whether it can be null, which rules it out for cursors.
+    pub nullable: bool,
+    /// Whether it is left out of the projection unless asked for by the caller, e.g. fields
+    /// only owners may see.
+    pub restricted: bool,
+    /// Keys of a JSONB field that may be filtered by, see [`ListQuery::filter_key`].
+    pub keys: &'static [JsonKey],
+}
+
+impl Field {
+    pub const fn new(name: &'static str, field_type: FieldType) -> Self {
+        Self {
+            name,
+            field_type,
+            sortable: true,
+            nullable: false,
+            restricted: false,
+            keys: &[],
+        }
+    }
+
+    pub const fn nullable(self) -> Self {
+        Self {
+            nullable: true,
+            ..self
+        }
+    }
+
+    pub const fn unsortable(self) -> Self {
+        Self {
+            sortable: false,
+            ..self
+        }
+    }
+
+    pub const fn restricted(self) -> Self {
+        Self {
+            restricted: true,
+            ..self
+        }
+    }
+
+    pub const fn keys(self, keys: &'static [JsonKey]) -> Self {
+        Self { keys, ..self }
+    }
+
+    pub fn key(&self, name: &str) -> Result<&'static JsonKey, QueryError> {
+        self.keys
+            .iter()
+            .find(|key| key.name == name)
+            .ok_or_else(|| QueryError::UnknownField(format!("{}.{name}", self.name)))
+    }
+}
+
+/// Whitelist of the fields of a table that a list endpoint may project, filter and sort by.
+/// The table must have an integer `id` column, which breaks ties in the sort order.
+#[derive(Debug)]
+pub struct Resource {
+    pub table: &'static str,
+    pub fields: &'static [Field],
+}
+
+impl Resource {
+    pub fn field(&self, name: &str) -> Result<&'static Field, QueryError> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| QueryError::UnknownField(name.to_string()))
+    }
+
+    /// The fields named in a comma separated list like `id,name`, unknown ones skipped, or all
+    /// unrestricted fields when there is no list. Restricted fields are only included with
+    /// `include_restricted`.
+    pub fn projection(
+        &self,
+        requested: Option<&str>,
+        include_restricted: bool,
+    ) -> Result<Vec<&'static str>, QueryError> {
+        let visible = |field: &&Field| include_restricted || !field.restricted;
+        let fields: Vec<&'static str> = match requested {
+            Some(requested) => requested
+                .split(',')
+                .map(str::trim)
+                .filter_map(|name| self.fields.iter().filter(visible).find(|f| f.name == name))
+                .map(|field| field.name)
+                .collect(),
+            None => self
+                .fields
+                .iter()
+                .filter(|field| !field.restricted)
+                .map(|field| field.name)
+                .collect(),
+        };
+        if fields.is_empty() {
+            return Err(QueryError::NoFields);
+        }
+        Ok(fields)
+    }
+}
+
+/// Why a list query could not be built, worth a 400 response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    UnknownField(String),
+    NoFields,
+    NotFilterable(&'static str),
+    NotSortable(&'static str),
+    InvalidValue { field: &'static str, value: String },
+    InvalidCursor,
+    CursorMismatch,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::UnknownField(name) => write!(f, "Unknown field {name}"),
+            QueryError::NoFields => f.write_str("No valid fields provided."),
+            QueryError::NotFilterable(name) => write!(f, "Cannot filter by {name} this way"),
+            QueryError::NotSortable(name) => write!(f, "Cannot sort by {name}"),
+            QueryError::InvalidValue { field, value } => {
+                write!(f, "Invalid value {value:?} for {field}")
+            }
+            QueryError::InvalidCursor => f.write_str("Invalid cursor"),
+            QueryError::CursorMismatch => {
+                f.write_str("Cursor belongs to a listing with another sort order")
+            }
+        }
+    }
+}
+
+/// A filter on one field or JSONB key. Values are given as text and parsed by the type of the
+/// field or key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter<'a> {
+    Eq(&'a str),
+    /// Case insensitive substring match, text fields only.
+    Ilike(&'a str),
+    In(Vec<&'a str>),
+    /// Inclusive lower and exclusive upper bound, either may be left open.
+    Range {
+        from: Option<&'a str>,
+        to: Option<&'a str>,
+    },
+    /// Inclusive lower bound.
+    AtLeast(&'a str),
+    /// Inclusive upper bound.
+    AtMost(&'a str),
+    /// All the values are in the array, string array keys only.
+    Contains(Vec<&'a str>),
+    /// The field is not null, or the key is present.
+    Exists,
+}
+
+/// A value bound to a query parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i32),
+    Text(String),
+    Timestamp(NaiveDateTime),
+    Json(serde_json::Value),
+    /// Text cast to the named type, e.g. an enum.
+    Cast(&'static str, String),
+}
+
+impl Value {
+    /// The value of `field` written as `raw`.
+    pub fn parse(field: &Field, raw: &str) -> Result<Self, QueryError> {
+        let invalid = || QueryError::InvalidValue {
+            field: field.name,
+            value: raw.to_string(),
+        };
+        let raw = raw.trim();
+        match field.field_type {
+            FieldType::Integer => raw.parse().map(Value::Integer).map_err(|_| invalid()),
+            FieldType::Text => Ok(Value::Text(raw.to_string())),
+            FieldType::Timestamp => parse_timestamp(raw)
+                .map(Value::Timestamp)
+                .ok_or_else(invalid),
+            FieldType::Enum(type_name, values) if values.contains(&raw) => {
+                Ok(Value::Cast(type_name, raw.to_string()))
+            }
+            FieldType::Enum(..) => Err(invalid()),
+            FieldType::Jsonb => Err(QueryError::NotFilterable(field.name)),
+        }
+    }
+}
+
+/// A finite number, as JSON numbers cannot be anything else.
+fn parse_number(raw: &str) -> Option<f64> {
+    raw.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// RFC 3339 timestamps are converted to UTC, timestamps without an offset and dates taken as
+/// they are.
+fn parse_timestamp(raw: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(raw)
+        .map(|timestamp| timestamp.naive_utc())
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S%.f").ok())
+        .or_else(|| NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S%.f").ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// A piece of a `WHERE` condition, SQL written by the builder or a value to bind.
+#[derive(Debug, Clone)]
+enum Part {
+    Sql(String),
+    Bind(Value),
+}
+
+/// Builds the list query of a [`Resource`] with every value bound as a parameter. Field names
+/// only get into the SQL after being checked against the whitelist.
+///
+/// Each row has the projected fields as JSON in `item`, the sort value as text in `sort_key` and
+/// the id in `row_id`, the latter two to make [`Cursor`]s of.
+#[derive(Debug, Clone)]
+pub struct ListQuery<'r> {
+    resource: &'r Resource,
+    projection: Vec<&'static str>,
+    filters: Vec<Vec<Part>>,
+    keyset: Option<Vec<Part>>,
+    sort: &'static Field,
+    descending: bool,
+    backwards: bool,
+    limit: i64,
+    offset: i64,
+}
+
+impl<'r> ListQuery<'r> {
+    /// Query of the `projection` fields of all rows, by descending id.
+    pub fn new(resource: &'r Resource, projection: Vec<&'static str>) -> Result<Self, QueryError> {
+        Ok(Self {
+            resource,
+            projection,
+            filters: Vec::new(),
+            keyset: None,
+            sort: resource.field("id")?,
+            descending: true,
+            backwards: false,
+            limit: i64::MAX,
+            offset: 0,
+        })
+    }
+
+    pub fn filter(mut self, name: &str, filter: Filter) -> Result<Self, QueryError> {
+        let field = self.resource.field(name)?;
+        let column = column(field);
+        let parts = match filter {
+            Filter::Eq(raw) => vec![
+                Part::Sql(format!("{column} = ")),
+                Part::Bind(Value::parse(field, raw)?),
+            ],
+            Filter::Ilike(raw) => {
+                if field.field_type != FieldType::Text {
+                    return Err(QueryError::NotFilterable(field.name));
+                }
+                vec![
+                    Part::Sql(format!("{column} ILIKE ")),
+                    Part::Bind(Value::Text(format!("%{}%", escape_like(raw)))),
+                ]
+            }
+            Filter::In(raws) => {
+                let mut parts = vec![Part::Sql(format!("{column} IN ("))];
+                for (i, raw) in raws.iter().enumerate() {
+                    if i > 0 {
+                        parts.push(Part::Sql(", ".to_string()));
+                    }
+                    parts.push(Part::Bind(Value::parse(field, raw)?));
+                }
+                if raws.is_empty() {
+                    // `IN ()` is not valid SQL, an empty list matches nothing
+                    parts = vec![Part::Sql("FALSE".to_string())];
+                } else {
+                    parts.push(Part::Sql(")".to_string()));
+                }
+                parts
+            }
+            Filter::Range { from, to } => {
+                let mut parts = Vec::new();
+                if let Some(from) = from {
+                    parts.push(Part::Sql(format!("{column} >= ")));
+                    parts.push(Part::Bind(Value::parse(field, from)?));
+                }
+                if let Some(to) = to {
+                    if !parts.is_empty() {
+                        parts.push(Part::Sql(" AND ".to_string()));
+                    }
+                    parts.push(Part::Sql(format!("{column} < ")));
+                    parts.push(Part::Bind(Value::parse(field, to)?));
+                }
+                if parts.is_empty() {
+                    return Ok(self);
+                }
+                parts
+            }
+            Filter::AtLeast(raw) => vec![
+                Part::Sql(format!("{column} >= ")),
+                Part::Bind(Value::parse(field, raw)?),
+            ],
+            Filter::AtMost(raw) => vec![
+                Part::Sql(format!("{column} <= ")),
+                Part::Bind(Value::parse(field, raw)?),
+            ],
+            Filter::Exists => vec![Part::Sql(format!("{column} IS NOT NULL"))],
+            Filter::Contains(_) => return Err(QueryError::NotFilterable(field.name)),
+        };
+        self.filters.push(parts);
+        Ok(self)
+    }
+
+    /// Filter by the `key` of the JSONB field `name`, with conditions a GIN index on the field
+    /// can serve: containment, key existence and jsonpath comparisons.
+    ///
+    /// Rows where the key holds a value of another type never match.
+    pub fn filter_key(mut self, name: &str, key: &str, filter: Filter) -> Result<Self, QueryError> {
+        let field = self.resource.field(name)?;
+        let key = field.key(key)?;
+        let column = column(field);
+        let parts = match (filter, key.key_type) {
+            (Filter::Exists, _) => vec![
+                Part::Sql(format!("{column} ? ")),
+                Part::Bind(Value::Text(key.name.to_string())),
+            ],
+            (Filter::Contains(values), KeyType::StringArray) => {
+                let values: Vec<&str> = values
+                    .iter()
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+                    .collect();
+                if values.is_empty() {
+                    return Ok(self);
+                }
+                vec![
+                    Part::Sql(format!("{column} @> ")),
+                    Part::Bind(Value::Json(json!({ key.name: values }))),
+                ]
+            }
+            (Filter::AtLeast(raw), KeyType::Number) => json_path_compare(&column, key, ">=", raw)?,
+            (Filter::AtMost(raw), KeyType::Number) => json_path_compare(&column, key, "<=", raw)?,
+            _ => return Err(QueryError::NotFilterable(key.name)),
+        };
+        self.filters.push(parts);
+        Ok(self)
+    }
+
+    /// Sort by `name`, then by id in the same direction.
+    pub fn sort(mut self, name: &str, descending: bool) -> Result<Self, QueryError> {
+        let field = self.resource.field(name)?;
+        if !field.sortable {
+            return Err(QueryError::NotSortable(field.name));
+        }
+        self.sort = field;
+        self.descending = descending;
+        Ok(self)
+    }
+
+    /// Whether the sort field can be paginated by cursor, null values would end every row
+    /// comparison.
+    pub fn supports_cursors(&self) -> bool {
+        !self.sort.nullable
+    }
+
+    /// Only the rows on the `cursor.direction` side of the cursor row. Set the sort order first.
+    pub fn at(mut self, cursor: &Cursor) -> Result<Self, QueryError> {
+        if !cursor.matches(self.sort.name, self.descending) {
+            return Err(QueryError::CursorMismatch);
+        }
+        if !self.supports_cursors() {
+            return Err(QueryError::NotSortable(self.sort.name));
+        }
+        let key = Value::parse(self.sort, &cursor.key).map_err(|_| QueryError::InvalidCursor)?;
+        self.keyset = Some(vec![
+            Part::Sql(format!(
+                "({}, {TABLE_ALIAS}.id) {} (",
+                column(self.sort),
+                cursor.operator()
+            )),
+            Part::Bind(key),
+            Part::Sql(", ".to_string()),
+            Part::Bind(Value::Integer(cursor.id)),
+            Part::Sql(")".to_string()),
+        ]);
+        // The rows before the cursor are fetched in reverse, see `backwards`
+        self.backwards = cursor.direction == Direction::Prev;
+        Ok(self)
+    }
+
+    /// Whether the rows come back in reverse sort order and have to be flipped, as for pages
+    /// before a cursor.
+    pub fn backwards(&self) -> bool {
+        self.backwards
+    }
+
+    pub fn page(mut self, limit: i64, offset: i64) -> Self {
+        self.limit = limit;
+        self.offset = offset;
+        self
+    }
+
+    /// The query of the page.
+    pub fn build(&self) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+        let mut sql = String::new();
+        let mut binds = Vec::new();
+        let projection = self
+            .projection
+            .iter()
+            .map(|name| format!("{TABLE_ALIAS}.{name}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        sql.push_str(&format!(
+            "SELECT (SELECT row_to_json(p) FROM (SELECT {projection}) p) AS item, \
+             {sort}::text AS sort_key, {TABLE_ALIAS}.id AS row_id FROM {} {TABLE_ALIAS}",
+            self.resource.table,
+            sort = column(self.sort),
+        ));
+        let conditions = self.filters.iter().chain(&self.keyset);
+        push_where(&mut sql, &mut binds, conditions);
+
+        let order = if self.descending != self.backwards {
+            "DESC"
+        } else {
+            "ASC"
+        };
+        sql.push_str(&format!(
+            " ORDER BY {} {order}, {TABLE_ALIAS}.id {order} LIMIT ${} OFFSET ${}",
+            column(self.sort),
+            binds.len() + 1,
+            binds.len() + 2,
+        ));
+        bind_all(sql, binds)
+            .bind::<BigInt, _>(self.limit)
+            .bind::<BigInt, _>(self.offset)
+    }
+
+    /// The query counting all matching rows as `total`, regardless of cursor and page.
+    pub fn build_count(&self) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+        let mut sql = format!(
+            "SELECT COUNT(*) AS total FROM {} {TABLE_ALIAS}",
+            self.resource.table
+        );
+        let mut binds = Vec::new();
+        push_where(&mut sql, &mut binds, self.filters.iter());
+        bind_all(sql, binds)
+    }
+}
+
+fn column(field: &Field) -> String {
+    format!("{TABLE_ALIAS}.{}", field.name)
+}
+
+/// Condition comparing the number at `key` of a JSONB column with `raw`.
+fn json_path_compare(
+    column: &str,
+    key: &JsonKey,
+    operator: &str,
+    raw: &str,
+) -> Result<Vec<Part>, QueryError> {
+    let number = parse_number(raw).ok_or_else(|| QueryError::InvalidValue {
+        field: key.name,
+        value: raw.to_string(),
+    })?;
+    // jsonpath has no parameters, the path is safe to write out as the key comes from the
+    // whitelist and the number is parsed
+    Ok(vec![
+        Part::Sql(format!("{column} @@ ")),
+        Part::Bind(Value::Cast(
+            "jsonpath",
+            format!("$.\"{}\" {operator} {number}", key.name),
+        )),
+    ])
+}
+
+/// Escape the `LIKE` wildcards in `value`, so it matches literally.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Append the `WHERE` clause of `conditions` to `sql`, numbering the parameters after `binds`.
+fn push_where<'a>(
+    sql: &mut String,
+    binds: &mut Vec<Value>,
+    conditions: impl Iterator<Item = &'a Vec<Part>>,
+) {
+    for (i, condition) in conditions.enumerate() {
+        sql.push_str(if i == 0 { " WHERE (" } else { " AND (" });
+        for part in condition {
+            match part {
+                Part::Sql(fragment) => sql.push_str(fragment),
+                Part::Bind(value) => {
+                    binds.push(value.clone());
+                    match value {
+                        Value::Cast(type_name, _) => {
+                            sql.push_str(&format!("CAST(${} AS {type_name})", binds.len()))
+                        }
+                        _ => sql.push_str(&format!("${}", binds.len())),
+                    }
+                }
+            }
+        }
+        sql.push(')');
+    }
+}
+
+fn bind_all(sql: String, binds: Vec<Value>) -> BoxedSqlQuery<'static, Pg, SqlQuery> {
+    binds.into_iter().fold(
+        diesel::sql_query(sql).into_boxed(),
+        |query, value| match value {
+            Value::Integer(value) => query.bind::<Integer, _>(value),
+            Value::Text(value) | Value::Cast(_, value) => query.bind::<Text, _>(value),
+            Value::Timestamp(value) => query.bind::<Timestamp, _>(value),
+            Value::Json(value) => query.bind::<Jsonb, _>(value),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::debug_query;
+
+    use super::*;
+
+    const ITEMS: Resource = Resource {
+        table: "items",
+        fields: &[
+            Field::new("id", FieldType::Integer),
+            Field::new("name", FieldType::Text),
+            Field::new("kind", FieldType::Enum("item_kind", &["tool", "part"])),
+            Field::new("note", FieldType::Text).nullable(),
+            Field::new("details", FieldType::Jsonb)
+                .nullable()
+                .unsortable()
+                .keys(&[
+                    JsonKey::new("tags", KeyType::StringArray),
+                    JsonKey::new("weight", KeyType::Number),
+                ]),
+            Field::new("created_at", FieldType::Timestamp),
+            Field::new("secret", FieldType::Text).restricted(),
+        ],
+    };
+
+    fn sql(query: &BoxedSqlQuery<'static, Pg, SqlQuery>) -> String {
+        debug_query::<Pg, _>(query).to_string()
+    }
+
+    fn items(fields: &[&'static str]) -> ListQuery<'static> {
+        ListQuery::new(&ITEMS, fields.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn projection_keeps_known_fields_in_order() {
+        assert_eq!(
+            ITEMS.projection(Some("name, id,bogus"), false).unwrap(),
+            ["name", "id"]
+        );
+        assert_eq!(
+            ITEMS.projection(None, false).unwrap(),
+            ["id", "name", "kind", "note", "details", "created_at"]
+        );
+    }
+
+    #[test]
+    fn projection_hides_restricted_fields_unless_allowed() {
+        assert_eq!(
+            ITEMS.projection(Some("secret"), false),
+            Err(QueryError::NoFields)
+        );
+        assert_eq!(
+            ITEMS.projection(Some("id,secret"), true).unwrap(),
+            ["id", "secret"]
+        );
+    }
+
+    #[test]
+    fn builds_unfiltered_page() {
+        let query = items(&["id", "name"]).page(10, 20).build();
+        assert_eq!(
+            sql(&query),
+            "SELECT (SELECT row_to_json(p) FROM (SELECT t.id, t.name) p) AS item, \
+             t.id::text AS sort_key, t.id AS row_id FROM items t \
+             ORDER BY t.id DESC, t.id DESC LIMIT $1 OFFSET $2 -- binds: [10, 20]"
+        );
+    }
+
+    #[test]
+    fn binds_filters_in_order() {
+        let query = items(&["id"])
+            .filter("name", Filter::Ilike("50%_off"))
+            .unwrap()
+            .filter("kind", Filter::In(vec!["tool", "part"]))
+            .unwrap()
+            .filter("id", Filter::Eq("7"))
+            .unwrap()
+            .page(5, 0)
+            .build();
+        assert_eq!(
+            sql(&query),
+            "SELECT (SELECT row_to_json(p) FROM (SELECT t.id) p) AS item, \
+             t.id::text AS sort_key, t.id AS row_id FROM items t \
+             WHERE (t.name ILIKE $1) \
+             AND (t.kind IN (CAST($2 AS item_kind), CAST($3 AS item_kind))) \
+             AND (t.id = $4) \
+             ORDER BY t.id DESC, t.id DESC LIMIT $5 OFFSET $6 \
+             -- binds: [\"%50\\\\%\\\\_off%\", \"tool\", \"part\", 7, 5, 0]"
+        );
+    }
+
+    #[test]
+    fn builds_ranges_with_open_ends() {
+        let query = items(&["id"])
+            .filter(
+                "created_at",
+                Filter::Range {
+                    from: Some("2026-01-01"),
+                    to: Some("2026-02-01T12:00:00Z"),
+                },
+            )
+            .unwrap()
+            .filter(
+                "id",
+                Filter::Range {
+                    from: None,
+                    to: Some("100"),
+                },
+            )
+            .unwrap()
+            .filter(
+                "id",
+                Filter::Range {
+                    from: None,
+                    to: None,
+                },
+            )
+            .unwrap()
+            .build_count();
+        assert_eq!(
+            sql(&query),
+            "SELECT COUNT(*) AS total FROM items t \
+             WHERE (t.created_at >= $1 AND t.created_at < $2) AND (t.id < $3) \
+             -- binds: [2026-01-01T00:00:00, 2026-02-01T12:00:00, 100]"
+        );
+    }
+
+    #[test]
+    fn empty_in_matches_nothing() {
+        let query = items(&["id"])
+            .filter("id", Filter::In(vec![]))
+            .unwrap()
+            .build_count();
+        assert_eq!(
+            sql(&query),
+            "SELECT COUNT(*) AS total FROM items t WHERE (FALSE) -- binds: []"
+        );
+    }
+
+    #[test]
+    fn sorts_and_pages_by_cursor() {
+        let cursor = Cursor {
+            sort_by: "name".to_string(),
+            descending: false,
+            key: "bolt".to_string(),
+            id: 12,
+            direction: Direction::Next,
+        };
+        let query = items(&["id"])
+            .filter("kind", Filter::Eq("tool"))
+            .unwrap()
+            .sort("name", false)
+            .unwrap()
+            .at(&cursor)
+            .unwrap()
+            .page(3, 0);
+        assert!(!query.backwards());
+        assert_eq!(
+            sql(&query.build()),
+            "SELECT (SELECT row_to_json(p) FROM (SELECT t.id) p) AS item, \
+             t.name::text AS sort_key, t.id AS row_id FROM items t \
+             WHERE (t.kind = CAST($1 AS item_kind)) AND ((t.name, t.id) > ($2, $3)) \
+             ORDER BY t.name ASC, t.id ASC LIMIT $4 OFFSET $5 \
+             -- binds: [\"tool\", \"bolt\", 12, 3, 0]"
+        );
+        // The total ignores the cursor
+        assert_eq!(
+            sql(&query.build_count()),
+            "SELECT COUNT(*) AS total FROM items t \
+             WHERE (t.kind = CAST($1 AS item_kind)) -- binds: [\"tool\"]"
+        );
+    }
+
+    #[test]
+    fn fetches_rows_before_cursor_in_reverse() {
+        let cursor = Cursor {
+            sort_by: "created_at".to_string(),
+            descending: true,
+            key: "2026-10-18 09:59:29.75794".to_string(),
+            id: 20,
+            direction: Direction::Prev,
+        };
+        let query = items(&["id"])
+            .sort("created_at", true)
+            .unwrap()
+            .at(&cursor)
+            .unwrap()
+            .page(2, 0);
+        assert!(query.backwards());
+        assert_eq!(
+            sql(&query.build()),
+            "SELECT (SELECT row_to_json(p) FROM (SELECT t.id) p) AS item, \
+             t.created_at::text AS sort_key, t.id AS row_id FROM items t \
+             WHERE ((t.created_at, t.id) > ($1, $2)) \
+             ORDER BY t.created_at ASC, t.id ASC LIMIT $3 OFFSET $4 \
+             -- binds: [2026-10-18T09:59:29.757940, 20, 2, 0]"
+        );
+    }
+
+    #[test]
+    fn rejects_fields_and_values_outside_the_whitelist() {
+        assert_eq!(
+            items(&["id"])
+                .filter("id; DROP TABLE items", Filter::Eq("1"))
+                .err(),
+            Some(QueryError::UnknownField("id; DROP TABLE items".to_string()))
+        );
+        assert_eq!(
+            items(&["id"]).filter("id", Filter::Eq("one")).err(),
+            Some(QueryError::InvalidValue {
+                field: "id",
+                value: "one".to_string()
+            })
+        );
+        assert_eq!(
+            items(&["id"]).filter("kind", Filter::Eq("toy")).err(),
+            Some(QueryError::InvalidValue {
+                field: "kind",
+                value: "toy".to_string()
+            })
+        );
+        assert_eq!(
+            items(&["id"]).filter("id", Filter::Ilike("1")).err(),
+            Some(QueryError::NotFilterable("id"))
+        );
+        assert_eq!(
+            items(&["id"]).sort("details", true).err(),
+            Some(QueryError::NotSortable("details"))
+        );
+    }
+
+    #[test]
+    fn builds_json_key_filters() {
+        let query = items(&["id"])
+            .filter_key("details", "tags", Filter::Contains(vec!["red", " big "]))
+            .unwrap()
+            .filter_key("details", "weight", Filter::AtLeast("2.5"))
+            .unwrap()
+            .filter_key("details", "weight", Filter::AtMost("-1"))
+            .unwrap()
+            .filter_key("details", "tags", Filter::Exists)
+            .unwrap()
+            .filter_key("details", "tags", Filter::Contains(vec![""]))
+            .unwrap()
+            .build_count();
+        assert_eq!(
+            sql(&query),
+            "SELECT COUNT(*) AS total FROM items t \
+             WHERE (t.details @> $1) \
+             AND (t.details @@ CAST($2 AS jsonpath)) \
+             AND (t.details @@ CAST($3 AS jsonpath)) \
+             AND (t.details ? $4) \
+             -- binds: [Object {\"tags\": Array [String(\"red\"), String(\"big\")]}, \
+             \"$.\\\"weight\\\" >= 2.5\", \"$.\\\"weight\\\" <= -1\", \"tags\"]"
+        );
+    }
+
+    #[test]
+    fn rejects_json_keys_outside_the_whitelist() {
+        assert_eq!(
+            items(&["id"])
+                .filter_key("details", "owner", Filter::Exists)
+                .err(),
+            Some(QueryError::UnknownField("details.owner".to_string()))
+        );
+        assert_eq!(
+            items(&["id"])
+                .filter_key("details", "weight", Filter::AtLeast("NaN"))
+                .err(),
+            Some(QueryError::InvalidValue {
+                field: "weight",
+                value: "NaN".to_string()
+            })
+        );
+        assert_eq!(
+            items(&["id"])
+                .filter_key("details", "tags", Filter::AtLeast("1"))
+                .err(),
+            Some(QueryError::NotFilterable("tags"))
+        );
+        assert_eq!(
+            items(&["id"])
+                .filter("name", Filter::Contains(vec!["a"]))
+                .err(),
+            Some(QueryError::NotFilterable("name"))
+        );
+    }
+
+    #[test]
+    fn rejects_unusable_cursors() {
+        let cursor = Cursor {
+            sort_by: "note".to_string(),
+            descending: true,
+            key: "x".to_string(),
+            id: 1,
+            direction: Direction::Next,
+        };
+        assert_eq!(
+            items(&["id"]).at(&cursor).err(),
+            Some(QueryError::CursorMismatch)
+        );
+        assert_eq!(
+            items(&["id"]).sort("note", true).unwrap().at(&cursor).err(),
+            Some(QueryError::NotSortable("note"))
+        );
+        let cursor = Cursor {
+            sort_by: "id".to_string(),
+            key: "not a number".to_string(),
+            ..cursor
+        };
+        assert_eq!(
+            items(&["id"]).at(&cursor).err(),
+            Some(QueryError::InvalidCursor)
+        );
+    }
+}
@@ -1 +1,5 @@
+pub mod list_query;
+pub mod pagination;
+pub mod password_policy;
+pub mod phone;
 pub mod validation;
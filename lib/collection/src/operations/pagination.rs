@@ -0,0 +1,53 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{Deserialize, Serialize};
+
+/// Which side of the cursor row a page lies on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The rows after the cursor row in the sort order.
+    Next,
+    /// The rows before the cursor row in the sort order.
+    Prev,
+}
+
+/// Position in a listing sorted by `sort_by` and then by id, handed to clients as an opaque
+/// token. Unlike an offset it stays on the same row when rows are added or removed before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// Sort field and order of the listing, the cursor only makes sense in the same one.
+    pub sort_by: String,
+    pub descending: bool,
+    /// Sort value of the cursor row, in its text form.
+    pub key: String,
+    pub id: i32,
+    pub direction: Direction,
+}
+
+impl Cursor {
+    /// The token clients send back to get the page the cursor points at.
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor serializes to JSON");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// The cursor of a token made by [`Cursor::encode`], `None` for anything else.
+    pub fn decode(token: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(token.trim()).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Whether the cursor was made for a listing sorted by `sort_by` in the given order.
+    pub fn matches(&self, sort_by: &str, descending: bool) -> bool {
+        self.sort_by == sort_by && self.descending == descending
+    }
+
+    /// Comparison operator selecting the rows of the page in SQL, as in
+    /// `(sort_field, id) < ($key, $id)`.
+    pub fn operator(&self) -> &'static str {
+        match (self.direction, self.descending) {
+            (Direction::Next, false) | (Direction::Prev, true) => ">",
+            (Direction::Next, true) | (Direction::Prev, false) => "<",
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{borrow::Cow, collections::HashSet, env, sync::LazyLock};
+
+use validator::ValidationError;
+
+/// Most common passwords from public breach corpora, one per line.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+static COMMON_PASSWORD_SET: LazyLock<HashSet<&'static str>> =
+    LazyLock::new(|| COMMON_PASSWORDS.lines().map(str::trim).collect());
+
+/// Identifying parts shorter than this are too likely to occur by chance to be rejected.
+const MIN_IDENTITY_LENGTH: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    fn name(self) -> &'static str {
+        match self {
+            CharacterClass::Lowercase => "lowercase",
+            CharacterClass::Uppercase => "uppercase",
+            CharacterClass::Digit => "digit",
+            CharacterClass::Symbol => "symbol",
+        }
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            CharacterClass::Lowercase => c.is_lowercase(),
+            CharacterClass::Uppercase => c.is_uppercase(),
+            CharacterClass::Digit => c.is_numeric(),
+            CharacterClass::Symbol => !c.is_alphanumeric(),
+        }
+    }
+}
+
+/// Rules a new password must satisfy.
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub required_classes: Vec<CharacterClass>,
+}
+
+impl PasswordPolicy {
+    /// Reads `PASSWORD_MIN_LENGTH` (8) and `PASSWORD_REQUIRED_CLASSES`, a comma separated list
+    /// of `lowercase`, `uppercase`, `digit` and `symbol` (all but `symbol`).
+    pub fn from_env() -> Self {
+        let min_length = env::var("PASSWORD_MIN_LENGTH")
+            .ok()
+            .map(|value| {
+                value
+                    .parse()
+                    .expect("PASSWORD_MIN_LENGTH must be a positive number")
+            })
+            .unwrap_or(8);
+        let required_classes = match env::var("PASSWORD_REQUIRED_CLASSES") {
+            Ok(classes) => classes
+                .split(',')
+                .map(str::trim)
+                .filter(|class| !class.is_empty())
+                .map(|class| match class {
+                    "lowercase" => CharacterClass::Lowercase,
+                    "uppercase" => CharacterClass::Uppercase,
+                    "digit" => CharacterClass::Digit,
+                    "symbol" => CharacterClass::Symbol,
+                    class => panic!("PASSWORD_REQUIRED_CLASSES: unknown class {class}"),
+                })
+                .collect(),
+            Err(_) => vec![
+                CharacterClass::Lowercase,
+                CharacterClass::Uppercase,
+                CharacterClass::Digit,
+            ],
+        };
+        Self {
+            min_length,
+            required_classes,
+        }
+    }
+
+    /// Validate `password` against the policy.
+    ///
+    /// `identities` are values identifying the user, such as their email and name, which the
+    /// password must not contain.
+    pub fn validate(&self, password: &str, identities: &[&str]) -> Result<(), ValidationError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            let mut err = ValidationError::new("password_length");
+            err.add_param(Cow::from("min"), &self.min_length);
+            return Err(err);
+        }
+
+        let missing: Vec<&str> = self
+            .required_classes
+            .iter()
+            .filter(|class| !password.chars().any(|c| class.matches(c)))
+            .map(|class| class.name())
+            .collect();
+        if !missing.is_empty() {
+            let mut err = ValidationError::new("password_classes");
+            err.add_param(Cow::from("missing"), &missing);
+            return Err(err);
+        }
+
+        let lowercase = password.to_lowercase();
+        if identity_parts(identities).any(|part| lowercase.contains(&part)) {
+            return Err(ValidationError::new("password_contains_identity"));
+        }
+
+        if COMMON_PASSWORD_SET.contains(lowercase.as_str()) {
+            return Err(ValidationError::new("password_common"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Lowercase identities, email local parts and words of names long enough to check.
+fn identity_parts<'a>(identities: &'a [&str]) -> impl Iterator<Item = String> + 'a {
+    identities.iter().flat_map(|identity| {
+        let identity = identity.trim().to_lowercase();
+        let mut parts = vec![identity.clone()];
+        if let Some((local, _)) = identity.split_once('@') {
+            parts.push(local.to_string());
+        }
+        parts.extend(identity.split_whitespace().map(str::to_string));
+        parts
+            .into_iter()
+            .filter(|part| part.chars().count() >= MIN_IDENTITY_LENGTH)
+    })
+}
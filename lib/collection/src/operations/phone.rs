@@ -0,0 +1,41 @@
+use std::{borrow::Cow, env};
+
+use phonenumber::{Mode, country};
+use validator::ValidationError;
+
+/// Parses phone numbers into E.164, the form they are stored and compared in.
+#[derive(Debug, Clone, Copy)]
+pub struct PhoneNumbers {
+    /// Region of numbers given without a country code.
+    pub default_region: country::Id,
+}
+
+impl PhoneNumbers {
+    /// Reads `PHONE_DEFAULT_REGION`, an ISO 3166 country code, `IN` by default.
+    pub fn from_env() -> Self {
+        let default_region = env::var("PHONE_DEFAULT_REGION")
+            .ok()
+            .map(|value| {
+                value.trim().to_uppercase().parse().unwrap_or_else(|_| {
+                    panic!("PHONE_DEFAULT_REGION must be an ISO 3166 country code, got {value}")
+                })
+            })
+            .unwrap_or(country::Id::IN);
+        Self { default_region }
+    }
+
+    /// The E.164 form of `input`, e.g. "+919876543210" for "+91 98765 43210" or "9876543210"
+    /// in India.
+    pub fn normalize(&self, input: &str) -> Result<String, ValidationError> {
+        let number = phonenumber::parse(Some(self.default_region), input.trim())
+            .ok()
+            .filter(phonenumber::is_valid)
+            .ok_or_else(|| {
+                ValidationError::new("phone_number").with_message(Cow::Borrowed(
+                    "Please enter a valid phone number, including the country code if it is \
+                     not local",
+                ))
+            })?;
+        Ok(number.format().mode(Mode::E164).to_string())
+    }
+}
@@ -108,6 +108,27 @@ fn describe_error(
             None => err.to_string(),
         },
         "not_empty" => "value invalid, must not be empty".to_string(),
+        "password_length" => match params.get("min") {
+            Some(min) => format!("password must be at least {min} characters"),
+            None => err.to_string(),
+        },
+        "password_classes" => match params.get("missing") {
+            Some(Value::Array(missing)) => format!(
+                "password must contain at least one character of each class: {}",
+                missing
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            _ => err.to_string(),
+        },
+        "password_contains_identity" => {
+            "password must not contain your email address or name".to_string()
+        }
+        "password_common" => {
+            "password is too common, it appears in lists of breached passwords".to_string()
+        }
         "closed_line" => {
             "value invalid, the first and the last points should be same to form a closed line"
                 .to_string()
@@ -0,0 +1,8 @@
+ALTER TABLE users DROP COLUMN token_version;
+
+-- Postgres cannot drop a value from an enum, recreate the type without it
+UPDATE users SET role = 'customer' WHERE role = 'admin';
+ALTER TYPE user_role RENAME TO user_role_old;
+CREATE TYPE user_role AS ENUM ('customer', 'professional');
+ALTER TABLE users ALTER COLUMN role TYPE user_role USING role::text::user_role;
+DROP TYPE user_role_old;
@@ -0,0 +1,4 @@
+ALTER TYPE user_role ADD VALUE IF NOT EXISTS 'admin';
+
+-- Bumped whenever the tokens issued to a user must stop being refreshed
+ALTER TABLE users ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,16 @@
+-- Keys for partner integrations, acting on behalf of the owning user
+CREATE TABLE api_keys (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    -- Visible part of the key, used to look it up and to recognize it in listings
+    prefix VARCHAR(16) NOT NULL UNIQUE,
+    key_hash VARCHAR(255) NOT NULL,
+    scopes TEXT[] NOT NULL,
+    expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
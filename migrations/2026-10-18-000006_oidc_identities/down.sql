@@ -0,0 +1,3 @@
+ALTER TABLE users ALTER COLUMN phone_number SET NOT NULL;
+
+DROP TABLE user_identities;
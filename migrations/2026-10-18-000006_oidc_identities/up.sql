@@ -0,0 +1,16 @@
+-- Accounts at OpenID Connect providers linked to users
+CREATE TABLE user_identities (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider VARCHAR(64) NOT NULL,
+    -- `sub` claim, stable per provider unlike the email
+    subject VARCHAR(255) NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    CONSTRAINT unique_identity UNIQUE (provider, subject)
+);
+
+CREATE INDEX user_identities_user_id_idx ON user_identities (user_id);
+
+-- Users signing up through a provider have not given a phone number yet
+ALTER TABLE users ALTER COLUMN phone_number DROP NOT NULL;
@@ -0,0 +1,5 @@
+DROP INDEX users_deletion_requested_at_idx;
+
+ALTER TABLE users
+    DROP COLUMN deletion_requested_at,
+    DROP COLUMN erased_at;
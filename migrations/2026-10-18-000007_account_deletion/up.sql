@@ -0,0 +1,7 @@
+-- Users asking for their account to be deleted are erased after a grace period
+ALTER TABLE users
+    ADD COLUMN deletion_requested_at TIMESTAMP,
+    ADD COLUMN erased_at TIMESTAMP;
+
+CREATE INDEX users_deletion_requested_at_idx ON users (deletion_requested_at)
+    WHERE deletion_requested_at IS NOT NULL AND erased_at IS NULL;
@@ -0,0 +1,28 @@
+-- Security relevant events, e.g. logins and changes to accounts
+CREATE TABLE audit_events (
+    id BIGSERIAL PRIMARY KEY,
+    event_type VARCHAR(64) NOT NULL,
+    -- User who did it, NULL for anonymous requests like failed logins
+    actor_id INTEGER REFERENCES users(id),
+    -- User it was done to
+    subject_id INTEGER REFERENCES users(id),
+    ip_address VARCHAR(45),
+    user_agent TEXT,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_events_actor_id_idx ON audit_events (actor_id, created_at);
+CREATE INDEX audit_events_subject_id_idx ON audit_events (subject_id, created_at);
+CREATE INDEX audit_events_event_type_idx ON audit_events (event_type, created_at);
+
+-- The log is append-only
+CREATE FUNCTION audit_events_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE UPDATE OR DELETE OR TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_events_append_only();
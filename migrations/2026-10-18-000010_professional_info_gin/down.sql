@@ -0,0 +1 @@
+DROP INDEX users_professional_info_idx;
@@ -0,0 +1,3 @@
+-- Serves the professional_info filters of the user listing: containment (@>), key existence (?)
+-- and jsonpath comparisons (@@). The default operator class, as jsonb_path_ops lacks `?`.
+CREATE INDEX users_professional_info_idx ON users USING GIN (professional_info);
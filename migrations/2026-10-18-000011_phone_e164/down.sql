@@ -0,0 +1 @@
+ALTER TABLE users DROP CONSTRAINT phone_number_e164;
@@ -0,0 +1,14 @@
+-- Phone numbers are stored in E.164. Numbers saved before the server normalized them have to be
+-- rewritten with `havenlyPro phones normalize` first, this migration refuses to run until then.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM users WHERE phone_number !~ '^\+[1-9][0-9]{1,14}$') THEN
+        RAISE EXCEPTION 'users has phone numbers that are not in E.164'
+            USING HINT = 'Run `havenlyPro phones normalize` and fix the numbers it lists, then '
+                'run the migrations again.';
+    END IF;
+END
+$$;
+
+ALTER TABLE users
+    ADD CONSTRAINT phone_number_e164 CHECK (phone_number ~ '^\+[1-9][0-9]{1,14}$');
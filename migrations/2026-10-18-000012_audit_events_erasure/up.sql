@@ -0,0 +1,24 @@
+-- Erasing an account removes the IP addresses and user agents from its audit events, the log
+-- stays append-only otherwise
+DROP TRIGGER audit_events_append_only ON audit_events;
+
+CREATE TRIGGER audit_events_append_only
+    BEFORE DELETE OR TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_events_append_only();
+
+CREATE FUNCTION audit_events_erase_only() RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.ip_address IS NULL AND NEW.user_agent IS NULL
+        AND (NEW.id, NEW.event_type, NEW.actor_id, NEW.subject_id, NEW.metadata, NEW.created_at)
+            IS NOT DISTINCT FROM
+            (OLD.id, OLD.event_type, OLD.actor_id, OLD.subject_id, OLD.metadata, OLD.created_at)
+    THEN
+        RETURN NEW;
+    END IF;
+    RAISE EXCEPTION 'audit_events is append-only, only IP addresses and user agents can be erased';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_erase_only
+    BEFORE UPDATE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION audit_events_erase_only();
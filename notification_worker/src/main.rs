@@ -1,28 +1,9 @@
+use api::models::notification::{NOTIFICATIONS_QUEUE, NotificationKind, NotificationMessage};
 use dotenvy::dotenv;
 use futures_lite::stream::StreamExt;
 use lapin::{Connection, ConnectionProperties, options, types::FieldTable};
 use redis::{AsyncCommands, Client};
-use serde::{Deserialize, Serialize};
 use std::env;
-use tokio;
-
-#[derive(Deserialize, Serialize, Debug)]
-#[serde(rename_all = "snake_case")]
-enum NotificationKind {
-    Otp,
-    Alert,
-    Marketing,
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct NotificationMessage {
-    user_id: i32,
-    email: Option<String>,
-    phone_number: Option<String>,
-    destinations: Vec<String>, // e.g. ["email", "whatsapp"]
-    kind: NotificationKind,
-    message: Option<String>, // Optional if OTP
-}
 
 async fn handle_otp(user_id: i32, redis_conn: &mut redis::aio::MultiplexedConnection) -> String {
     let otp = format!("{:06}", rand::random::<u32>() % 1_000_000);
@@ -34,13 +15,22 @@ async fn handle_otp(user_id: i32, redis_conn: &mut redis::aio::MultiplexedConnec
     otp
 }
 
-async fn send_email(to: &str, content: &str) {
-    println!("📧 Sending email to {}: {}", to, content);
+// Text of a notification as it may appear in logs
+fn loggable<'a>(kind: &NotificationKind, content: &'a str) -> &'a str {
+    if kind.is_secret() {
+        "[redacted]"
+    } else {
+        content
+    }
+}
+
+async fn send_email(to: &str, kind: &NotificationKind, content: &str) {
+    println!("📧 Sending email to {}: {}", to, loggable(kind, content));
     // TODO: Integrate lettre crate
 }
 
-async fn send_whatsapp(to: &str, content: &str) {
-    println!("💬 Sending WhatsApp to {}: {}", to, content);
+async fn send_whatsapp(to: &str, kind: &NotificationKind, content: &str) {
+    println!("💬 Sending WhatsApp to {}: {}", to, loggable(kind, content));
     // TODO: Integrate WhatsApp Cloud API
 }
 
@@ -61,7 +51,7 @@ async fn main() {
 
     channel
         .queue_declare(
-            "notifications",
+            NOTIFICATIONS_QUEUE,
             options::QueueDeclareOptions::default(),
             FieldTable::default(),
         )
@@ -70,7 +60,7 @@ async fn main() {
 
     let mut consumer = channel
         .basic_consume(
-            "notifications",
+            NOTIFICATIONS_QUEUE,
             "worker",
             options::BasicConsumeOptions::default(),
             FieldTable::default(),
@@ -89,14 +79,16 @@ async fn main() {
         let body = message.data;
         let key = format!("notification:{}", message.delivery_tag);
 
-        // Save raw payload to Redis
-        redis_conn
-            .set::<_, _, ()>(key.clone(), &body)
-            .await
-            .unwrap();
-
         // Deserialize and handle notification
         if let Ok(notification) = serde_json::from_slice::<NotificationMessage>(&body) {
+            // Save raw payload to Redis, unless it carries a credential
+            if !notification.kind.is_secret() {
+                redis_conn
+                    .set::<_, _, ()>(key.clone(), &body)
+                    .await
+                    .unwrap();
+            }
+
             let content = match notification.kind {
                 NotificationKind::Otp => {
                     Some(handle_otp(notification.user_id, &mut redis_conn).await)
@@ -109,12 +101,12 @@ async fn main() {
                     match channel.as_str() {
                         "email" => {
                             if let Some(email) = &notification.email {
-                                send_email(email, &text).await;
+                                send_email(email, &notification.kind, &text).await;
                             }
                         }
                         "whatsapp" => {
                             if let Some(phone) = &notification.phone_number {
-                                send_whatsapp(phone, &text).await;
+                                send_whatsapp(phone, &notification.kind, &text).await;
                             }
                         }
                         _ => println!("Unknown destination: {}", channel),
@@ -122,7 +114,11 @@ async fn main() {
                 }
             }
         } else {
-            println!("❌ Failed to parse notification message: {:?}", body);
+            // The payload itself may carry a credential
+            println!(
+                "❌ Failed to parse notification message {}",
+                message.delivery_tag
+            );
         }
 
         channel
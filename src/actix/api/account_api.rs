@@ -0,0 +1,741 @@
+use actix_web::{
+    HttpRequest, HttpResponse, delete, get,
+    http::header::{self, ContentType},
+    post,
+    web::{self, Data, ReqData},
+};
+use api::{
+    models::{
+        audit::AuditEventType,
+        notification::{NotificationKind, NotificationMessage},
+        user::{
+            ChangeEmailRequest, ChangePasswordRequest, ChangePhoneRequest, ConfirmChangeRequest,
+            DeleteAccountRequest, RawJsonExport, User, UserJWT, VerificationChannel,
+        },
+    },
+    schema::users,
+};
+use chrono::Utc;
+use collection::operations::{password_policy::PasswordPolicy, phone::PhoneNumbers, validation};
+use diesel::{dsl::now, prelude::*, sql_types::Integer};
+use serde_json::json;
+use validator::{Validate, ValidationErrors};
+
+use crate::{
+    DbPool,
+    actix::{
+        api::auth_api::{conflict, locked_out, reject_password},
+        audit,
+        auth::{ACCESS_TOKEN_COOKIE, CookieSettings, REFRESH_TOKEN_COOKIE, session_tokens},
+    },
+    common::{
+        contact_changes::{ChangeConfirmation, ContactChanges},
+        deletion_codes::DeletionCodes,
+        erasure::AccountErasure,
+        login_throttle::LoginThrottle,
+        notifications::Notifier,
+        passwordless::Redemption,
+        passwords::{PasswordHashing, Verification},
+        refresh_tokens::RefreshFamilies,
+        revocation::RevocationList,
+        sessions,
+        tokens::TokenIssuer,
+    },
+};
+
+#[post("/me/password")]
+#[allow(clippy::too_many_arguments)]
+async fn change_password(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    body: web::Json<ChangePasswordRequest>,
+    passwords: Data<PasswordHashing>,
+    policy: Data<PasswordPolicy>,
+    throttle: Data<LoginThrottle>,
+    families: Data<RefreshFamilies>,
+    revocations: Data<RevocationList>,
+    tokens: Data<TokenIssuer>,
+    cookies: Data<CookieSettings>,
+    notifier: Data<Notifier>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+    let user = match users::table.find(claims.id).first::<User>(&mut conn) {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+
+    if let Some(response) =
+        reject_wrong_password(&req, &throttle, &passwords, &user, &body.current_password).await
+    {
+        return response;
+    }
+    if let Some(response) = reject_password(
+        &policy,
+        &body.new_password,
+        &[&user.email, &user.name],
+        ValidationErrors::new(),
+    ) {
+        return response;
+    }
+    let hashed_password = match passwords.hash(&body.new_password) {
+        Ok(hash) => hash,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Hashing error: {e}")),
+    };
+
+    let updated = diesel::update(users::table.find(user.id))
+        .set((
+            users::password.eq(hashed_password),
+            users::updated_at.eq(now),
+        ))
+        .execute(&mut conn);
+    if let Err(e) = updated {
+        return HttpResponse::InternalServerError().body(format!("Database error: {e}"));
+    }
+
+    // Every token issued so far stops working, other devices lose their sessions while this one
+    // gets new tokens and stays logged in
+    if let Err(e) = revocations.revoke_all_before(user.id, Utc::now()).await {
+        return HttpResponse::InternalServerError().body(format!("Revocation store error: {e}"));
+    }
+    let families_to_revoke = match sessions::revoke_others(&mut conn, user.id, &claims.fid) {
+        Ok(fids) => fids,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+    for fid in families_to_revoke {
+        if let Err(e) = families.revoke(&fid).await {
+            return HttpResponse::InternalServerError().body(format!("Session store error: {e}"));
+        }
+    }
+    let session = match families.reissue(&claims.fid).await {
+        Ok(Some(refresh_jti)) => {
+            match session_tokens(&tokens, &user, claims.fid.clone(), refresh_jti) {
+                Ok((_, session)) => Some(session),
+                Err(e) => {
+                    return HttpResponse::InternalServerError().body(format!("Token error: {e}"));
+                }
+            }
+        }
+        // This session expired meanwhile, it has to log in again like the others
+        Ok(None) => None,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Session store error: {e}"));
+        }
+    };
+    audit::record_own(
+        &mut conn,
+        &req,
+        AuditEventType::PasswordChanged,
+        user.id,
+        json!({}),
+    );
+
+    let alert = contact_message(
+        user.id,
+        VerificationChannel::Email,
+        user.email,
+        NotificationKind::Alert,
+        "The password of your account was changed. If this was not you, reset your password \
+         and review your sessions."
+            .to_string(),
+    );
+    if let Err(e) = notifier.send(&alert).await {
+        log::error!(
+            "Failed to send password change alert to user {}: {e}",
+            user.id
+        );
+    }
+
+    let message = "Password changed, your other sessions have been logged out";
+    match session {
+        // Bearer clients hold their tokens themselves, like at login
+        Some(session) if req.headers().contains_key(header::AUTHORIZATION) => HttpResponse::Ok()
+            .json(json!({
+                "message": message,
+                "token_type": "Bearer",
+                "access_token": session.access_token,
+                "refresh_token": session.refresh_token,
+                "expires_in": tokens.access_lifetime().num_seconds()
+            })),
+        Some(session) => {
+            let [access_cookie, refresh_cookie] = session.into_cookies(&cookies);
+            HttpResponse::Ok()
+                .cookie(access_cookie)
+                .cookie(refresh_cookie)
+                .json(json!({ "message": message }))
+        }
+        None => HttpResponse::Ok()
+            .cookie(cookies.removal_cookie(ACCESS_TOKEN_COOKIE))
+            .cookie(cookies.removal_cookie(REFRESH_TOKEN_COOKIE))
+            .json(json!({ "message": "Password changed, please log in again" })),
+    }
+}
+
+#[post("/me/email")]
+async fn request_email_change(
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    body: web::Json<ChangeEmailRequest>,
+    changes: Data<ContactChanges>,
+    notifier: Data<Notifier>,
+) -> HttpResponse {
+    if let Err(errs) = body.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": validation::label_errors("Validation error in json", &errs)
+        }));
+    }
+    begin_change(
+        &pool,
+        &changes,
+        &notifier,
+        claims.id,
+        VerificationChannel::Email,
+        &body.email,
+    )
+    .await
+}
+
+#[post("/me/email/confirm")]
+async fn confirm_email_change(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    body: web::Json<ConfirmChangeRequest>,
+    changes: Data<ContactChanges>,
+    notifier: Data<Notifier>,
+) -> HttpResponse {
+    confirm_change(
+        &req,
+        &pool,
+        &changes,
+        &notifier,
+        claims.id,
+        VerificationChannel::Email,
+        &body.code,
+    )
+    .await
+}
+
+#[post("/me/phone")]
+async fn request_phone_change(
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    body: web::Json<ChangePhoneRequest>,
+    changes: Data<ContactChanges>,
+    notifier: Data<Notifier>,
+    phones: Data<PhoneNumbers>,
+) -> HttpResponse {
+    let phone_number = match phones.normalize(&body.phone_number) {
+        Ok(phone_number) => phone_number,
+        Err(err) => {
+            let mut errs = ValidationErrors::new();
+            errs.add("phone_number", err);
+            return HttpResponse::BadRequest().json(json!({
+                "error": validation::label_errors("Validation error in json", &errs)
+            }));
+        }
+    };
+    begin_change(
+        &pool,
+        &changes,
+        &notifier,
+        claims.id,
+        VerificationChannel::Phone,
+        &phone_number,
+    )
+    .await
+}
+
+#[post("/me/phone/confirm")]
+async fn confirm_phone_change(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    body: web::Json<ConfirmChangeRequest>,
+    changes: Data<ContactChanges>,
+    notifier: Data<Notifier>,
+) -> HttpResponse {
+    confirm_change(
+        &req,
+        &pool,
+        &changes,
+        &notifier,
+        claims.id,
+        VerificationChannel::Phone,
+        &body.code,
+    )
+    .await
+}
+
+#[get("/me/export")]
+async fn export_data(claims: ReqData<UserJWT>, pool: Data<DbPool>) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    let result = diesel::sql_query(
+        "SELECT json_build_object(
+            'exported_at', NOW(),
+            'user', (
+                SELECT row_to_json(u) FROM (
+                    SELECT id, name, email, role, phone_number, professional_info, created_at,
+                        updated_at, email_verified_at, phone_verified_at, deletion_requested_at
+                    FROM users WHERE id = $1
+                ) u
+            ),
+            'linked_accounts', COALESCE((
+                SELECT json_agg(i ORDER BY i.created_at) FROM (
+                    SELECT provider, email, created_at FROM user_identities WHERE user_id = $1
+                ) i
+            ), '[]'::json),
+            'sessions', COALESCE((
+                SELECT json_agg(s ORDER BY s.created_at) FROM (
+                    SELECT user_agent, ip_address, created_at, last_seen_at, revoked_at
+                    FROM user_sessions WHERE user_id = $1
+                ) s
+            ), '[]'::json),
+            'services', COALESCE((
+                SELECT json_agg(s ORDER BY s.id) FROM (
+                    SELECT id, category, description, base_price, created_at, updated_at
+                    FROM services WHERE professional_id = $1
+                ) s
+            ), '[]'::json),
+            'bookings', COALESCE((
+                SELECT json_agg(b ORDER BY b.id) FROM (
+                    SELECT id, customer_id, professional_id, service_id, scheduled_time, status,
+                        created_at, updated_at
+                    FROM bookings WHERE customer_id = $1 OR professional_id = $1
+                ) b
+            ), '[]'::json),
+            'transactions', COALESCE((
+                SELECT json_agg(t ORDER BY t.id) FROM (
+                    SELECT t.id, t.booking_id, t.amount, t.commission, t.platform_earnings,
+                        t.professional_earnings, t.created_at
+                    FROM transactions t JOIN bookings b ON b.id = t.booking_id
+                    WHERE b.customer_id = $1 OR b.professional_id = $1
+                ) t
+            ), '[]'::json)
+        )::text AS export",
+    )
+    .bind::<Integer, _>(claims.id)
+    .get_result::<RawJsonExport>(&mut conn);
+
+    match result {
+        Ok(row) => HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"havenlypro-export-{}.json\"",
+                    claims.id
+                ),
+            ))
+            .body(row.export),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {:?}", e)),
+    }
+}
+
+#[post("/me/delete/code")]
+async fn request_deletion_code(
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    codes: Data<DeletionCodes>,
+    notifier: Data<Notifier>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+    let email = match users::table
+        .find(claims.id)
+        .select(users::email)
+        .first::<String>(&mut conn)
+    {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+
+    let code = match codes.issue(claims.id).await {
+        Ok(code) => code,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Verification store error: {e}"));
+        }
+    };
+    let message = contact_message(
+        claims.id,
+        VerificationChannel::Email,
+        email,
+        NotificationKind::AccountDeletion,
+        codes.message(&code),
+    );
+    if let Err(e) = notifier.send(&message).await {
+        return HttpResponse::InternalServerError().body(format!("Failed to send code: {e}"));
+    }
+
+    HttpResponse::Accepted().json(json!({
+        "message": "A code to confirm the deletion has been sent to your email address"
+    }))
+}
+
+#[delete("/me")]
+#[allow(clippy::too_many_arguments)]
+async fn delete_account(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    body: web::Json<DeleteAccountRequest>,
+    passwords: Data<PasswordHashing>,
+    throttle: Data<LoginThrottle>,
+    codes: Data<DeletionCodes>,
+    erasure: Data<AccountErasure>,
+    revocations: Data<RevocationList>,
+    cookies: Data<CookieSettings>,
+    notifier: Data<Notifier>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+    let user = match users::table.find(claims.id).first::<User>(&mut conn) {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+    // Accounts created through a provider have a password nobody knows, the emailed code
+    // proves the owner is still at the keyboard instead
+    let rejected = match (&body.password, &body.code) {
+        (Some(password), None) => {
+            reject_wrong_password(&req, &throttle, &passwords, &user, password).await
+        }
+        (None, Some(code)) => reject_wrong_deletion_code(&codes, user.id, code).await,
+        _ => Some(
+            HttpResponse::BadRequest()
+                .body("Confirm with either your password or the code sent to your email"),
+        ),
+    };
+    if let Some(response) = rejected {
+        return response;
+    }
+
+    let erase_after = match erasure.request(&mut conn, user.id) {
+        Ok(erase_after) => erase_after,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+    let requested_at = Utc::now();
+    if let Err(e) = revocations.revoke_all_before(user.id, requested_at).await {
+        return HttpResponse::InternalServerError().body(format!("Revocation store error: {e}"));
+    }
+    if let Err(e) = sessions::revoke_all_before(&mut conn, user.id, requested_at) {
+        return HttpResponse::InternalServerError().body(format!("Database error: {e}"));
+    }
+    audit::record_own(
+        &mut conn,
+        &req,
+        AuditEventType::AccountDeletionRequested,
+        user.id,
+        json!({ "erase_after": erase_after }),
+    );
+
+    let notice = contact_message(
+        user.id,
+        VerificationChannel::Email,
+        user.email,
+        NotificationKind::Alert,
+        format!(
+            "Your account will be deleted on {}. Sign in before then if you want to keep it.",
+            erase_after.format("%Y-%m-%d")
+        ),
+    );
+    if let Err(e) = notifier.send(&notice).await {
+        log::error!("Failed to send deletion notice to user {}: {e}", user.id);
+    }
+
+    HttpResponse::Accepted()
+        .cookie(cookies.removal_cookie(ACCESS_TOKEN_COOKIE))
+        .cookie(cookies.removal_cookie(REFRESH_TOKEN_COOKIE))
+        .json(json!({
+            "message": "Your account will be deleted, sign in before then to cancel",
+            "erase_after": erase_after,
+        }))
+}
+
+/// Check the password of the signed in `user` before a sensitive change, counting wrong ones
+/// like failed logins. Returns the response to send when it is wrong.
+async fn reject_wrong_password(
+    req: &HttpRequest,
+    throttle: &LoginThrottle,
+    passwords: &PasswordHashing,
+    user: &User,
+    password: &str,
+) -> Option<HttpResponse> {
+    // A stolen session must not be able to guess the password faster than the login can
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    match throttle.locked_for(&user.email, ip).await {
+        Ok(Some(secs)) => return Some(locked_out(secs)),
+        Ok(None) => (),
+        Err(e) => {
+            return Some(
+                HttpResponse::InternalServerError().body(format!("Throttle store error: {e}")),
+            );
+        }
+    }
+    if let Verification::Invalid = passwords.verify(password, &user.password) {
+        return Some(match throttle.record_failure(&user.email, ip).await {
+            Ok(Some(lockout)) => locked_out(lockout.retry_after_secs),
+            Ok(None) => HttpResponse::Forbidden().body("Current password is incorrect"),
+            Err(e) => {
+                HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"))
+            }
+        });
+    }
+    if let Err(e) = throttle.record_success(&user.email).await {
+        return Some(
+            HttpResponse::InternalServerError().body(format!("Throttle store error: {e}")),
+        );
+    }
+    None
+}
+
+/// Check a code from [`request_deletion_code`], returns the response to send when it is wrong.
+async fn reject_wrong_deletion_code(
+    codes: &DeletionCodes,
+    user_id: i32,
+    code: &str,
+) -> Option<HttpResponse> {
+    match codes.redeem(user_id, code).await {
+        Ok(Redemption::Valid) => None,
+        Ok(Redemption::Invalid) => Some(HttpResponse::Forbidden().body("Invalid code")),
+        Ok(Redemption::Expired) => {
+            Some(HttpResponse::BadRequest().body("No code was requested or it expired"))
+        }
+        Ok(Redemption::TooManyAttempts) => {
+            Some(HttpResponse::BadRequest().body("Too many attempts, please request a new code"))
+        }
+        Err(e) => {
+            Some(HttpResponse::InternalServerError().body(format!("Verification store error: {e}")))
+        }
+    }
+}
+
+/// Send a code to the new `address`, the change applies once it is confirmed.
+async fn begin_change(
+    pool: &DbPool,
+    changes: &ContactChanges,
+    notifier: &Notifier,
+    user_id: i32,
+    channel: VerificationChannel,
+    address: &str,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    // Fail early on taken addresses, the unique constraints still decide on confirmation
+    let owner = match channel {
+        VerificationChannel::Email => users::table
+            .filter(users::email.eq(address))
+            .select(users::id)
+            .first::<i32>(&mut conn),
+        VerificationChannel::Phone => users::table
+            .filter(users::phone_number.eq(address))
+            .select(users::id)
+            .first::<i32>(&mut conn),
+    };
+    match owner.optional() {
+        Ok(None) => (),
+        Ok(Some(owner)) if owner == user_id => {
+            return HttpResponse::BadRequest().body("This address is already yours");
+        }
+        Ok(Some(_)) => {
+            let field = match channel {
+                VerificationChannel::Email => "email",
+                VerificationChannel::Phone => "phone number",
+            };
+            return HttpResponse::Conflict().body(format!("User with {} already exists", field));
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+
+    let code = match changes.begin(user_id, channel, address).await {
+        Ok(code) => code,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Verification store error: {e}"));
+        }
+    };
+    let message = contact_message(
+        user_id,
+        channel,
+        address.to_string(),
+        NotificationKind::ContactChange,
+        changes.message(&code),
+    );
+    if let Err(e) = notifier.send(&message).await {
+        return HttpResponse::InternalServerError().body(format!("Failed to send code: {e}"));
+    }
+
+    HttpResponse::Accepted().json(json!({
+        "message": "A confirmation code has been sent to the new address"
+    }))
+}
+
+/// Apply the pending change of the `channel` address if `code` is correct and let the old
+/// address know about it.
+async fn confirm_change(
+    req: &HttpRequest,
+    pool: &DbPool,
+    changes: &ContactChanges,
+    notifier: &Notifier,
+    user_id: i32,
+    channel: VerificationChannel,
+    code: &str,
+) -> HttpResponse {
+    let address = match changes.confirm(user_id, channel, code).await {
+        Ok(ChangeConfirmation::Confirmed(address)) => address,
+        Ok(ChangeConfirmation::Invalid) => return HttpResponse::BadRequest().body("Invalid code"),
+        Ok(ChangeConfirmation::Expired) => {
+            return HttpResponse::BadRequest().body("No pending change or the code expired");
+        }
+        Ok(ChangeConfirmation::TooManyAttempts) => {
+            return HttpResponse::BadRequest()
+                .body("Too many attempts, please request the change again");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Verification store error: {e}"));
+        }
+    };
+
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+    let user = match users::table.find(user_id).first::<User>(&mut conn) {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+
+    // The code proves the new address belongs to the user, so it counts as verified
+    let target = users::table.find(user_id);
+    let updated = match channel {
+        VerificationChannel::Email => diesel::update(target)
+            .set((
+                users::email.eq(&address),
+                users::email_verified_at.eq(now.nullable()),
+                users::updated_at.eq(now),
+            ))
+            .execute(&mut conn),
+        VerificationChannel::Phone => diesel::update(target)
+            .set((
+                users::phone_number.eq(&address),
+                users::phone_verified_at.eq(now.nullable()),
+                users::updated_at.eq(now),
+            ))
+            .execute(&mut conn),
+    };
+    match updated {
+        Ok(_) => (),
+        Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            err,
+        )) => return conflict(err.as_ref()),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+    // The addresses are left out, the log outlives the erasure of deleted accounts
+    let event_type = match channel {
+        VerificationChannel::Email => AuditEventType::EmailChanged,
+        VerificationChannel::Phone => AuditEventType::PhoneChanged,
+    };
+    audit::record_own(&mut conn, req, event_type, user_id, json!({}));
+
+    let (old_address, text) = match channel {
+        VerificationChannel::Email => (
+            Some(user.email),
+            "The email address of your account was changed. If this was not you, contact \
+             support right away.",
+        ),
+        VerificationChannel::Phone => (
+            user.phone_number,
+            "The phone number of your account was changed. If this was not you, contact support \
+             right away.",
+        ),
+    };
+    if let Some(old_address) = old_address {
+        let alert = contact_message(
+            user_id,
+            channel,
+            old_address,
+            NotificationKind::Alert,
+            text.to_string(),
+        );
+        // The change is done, a lost alert must not fail the request
+        if let Err(e) = notifier.send(&alert).await {
+            log::error!("Failed to send contact change alert to user {user_id}: {e}");
+        }
+    }
+
+    let field = match channel {
+        VerificationChannel::Email => "email",
+        VerificationChannel::Phone => "phone_number",
+    };
+    HttpResponse::Ok().json(json!({
+        "message": "Contact details updated",
+        field: address,
+    }))
+}
+
+/// Message with `text` sent to `address` through the channel it belongs to.
+fn contact_message(
+    user_id: i32,
+    channel: VerificationChannel,
+    address: String,
+    kind: NotificationKind,
+    text: String,
+) -> NotificationMessage {
+    let (email, phone_number, destination) = match channel {
+        VerificationChannel::Email => (Some(address), None, "email"),
+        VerificationChannel::Phone => (None, Some(address), "whatsapp"),
+    };
+    NotificationMessage {
+        user_id,
+        email,
+        phone_number,
+        destinations: vec![destination.to_string()],
+        kind,
+        message: Some(text),
+    }
+}
+
+pub fn configure_account_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(change_password);
+    cfg.service(request_email_change);
+    cfg.service(confirm_email_change);
+    cfg.service(request_phone_change);
+    cfg.service(confirm_phone_change);
+    cfg.service(export_data);
+    cfg.service(request_deletion_code);
+    cfg.service(delete_account);
+}
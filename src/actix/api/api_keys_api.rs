@@ -0,0 +1,161 @@
+use actix_web::{
+    HttpRequest, HttpResponse, delete, get, post,
+    web::{self, Data, Path, ReqData},
+};
+use api::{
+    models::{
+        api_key::{ApiKey, CreateApiKeyRequest, NewApiKey},
+        audit::AuditEventType,
+        user::UserJWT,
+    },
+    schema::api_keys,
+};
+use collection::operations::validation;
+use diesel::{dsl::now, prelude::*};
+use serde_json::json;
+use validator::Validate;
+
+use crate::{DbPool, actix::audit, common::api_keys as keys};
+
+#[post("/me/api-keys")]
+async fn create_api_key(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    body: web::Json<CreateApiKeyRequest>,
+) -> HttpResponse {
+    if let Err(errs) = body.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": validation::label_errors("Validation error in json", &errs)
+        }));
+    }
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    let request = body.into_inner();
+    let denied: Vec<&str> = request
+        .scopes
+        .iter()
+        .filter(|scope| !scope.allowed_for(claims.role))
+        .map(|scope| scope.as_str())
+        .collect();
+    if !denied.is_empty() {
+        return HttpResponse::Forbidden().json(json!({
+            "error": format!("Your account cannot grant the scopes {}", denied.join(", "))
+        }));
+    }
+    let generated = keys::generate();
+    let mut scopes: Vec<String> = request
+        .scopes
+        .iter()
+        .map(|scope| scope.as_str().to_string())
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+
+    let created = diesel::insert_into(api_keys::table)
+        .values(NewApiKey {
+            user_id: claims.id,
+            name: request.name,
+            prefix: generated.prefix,
+            key_hash: generated.hash,
+            scopes,
+            expires_at: request.expires_at.map(|expires_at| expires_at.naive_utc()),
+        })
+        .returning(ApiKey::as_returning())
+        .get_result(&mut conn);
+
+    match created {
+        // The key itself is only ever shown in this response
+        Ok(api_key) => {
+            audit::record_own(
+                &mut conn,
+                &req,
+                AuditEventType::ApiKeyCreated,
+                claims.id,
+                json!({ "api_key_id": api_key.id, "prefix": api_key.prefix, "scopes": api_key.scopes }),
+            );
+            HttpResponse::Created().json(json!({
+                "api_key": api_key,
+                "key": generated.key,
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+}
+
+#[get("/me/api-keys")]
+async fn get_api_keys(claims: ReqData<UserJWT>, pool: Data<DbPool>) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    let result = api_keys::table
+        .filter(api_keys::user_id.eq(claims.id))
+        .filter(api_keys::revoked_at.is_null())
+        .order(api_keys::created_at.desc())
+        .select(ApiKey::as_select())
+        .load(&mut conn);
+
+    match result {
+        Ok(api_keys) => HttpResponse::Ok().json(json!({ "api_keys": api_keys })),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+}
+
+#[delete("/me/api-keys/{key_id}")]
+async fn revoke_api_key(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    key_id: Path<i32>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+    let key_id = key_id.into_inner();
+
+    let revoked = diesel::update(
+        api_keys::table
+            .find(key_id)
+            .filter(api_keys::user_id.eq(claims.id))
+            .filter(api_keys::revoked_at.is_null()),
+    )
+    .set(api_keys::revoked_at.eq(now.nullable()))
+    .execute(&mut conn);
+
+    match revoked {
+        Ok(0) => HttpResponse::NotFound()
+            .body(format!("API key not found with the provided id {}", key_id)),
+        Ok(_) => {
+            audit::record_own(
+                &mut conn,
+                &req,
+                AuditEventType::ApiKeyRevoked,
+                claims.id,
+                json!({ "api_key_id": key_id }),
+            );
+            HttpResponse::Ok().json(json!({ "message": "API key revoked" }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+}
+
+pub fn configure_api_keys_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_api_key);
+    cfg.service(get_api_keys);
+    cfg.service(revoke_api_key);
+}
@@ -0,0 +1,84 @@
+use actix_web::{
+    HttpResponse, get,
+    web::{self, Data, Query},
+};
+use api::{
+    models::audit::{AuditEvent, AuditEventQuery},
+    schema::audit_events,
+};
+use diesel::prelude::*;
+use serde_json::json;
+
+use crate::{
+    DbPool,
+    actix::guards::{AdminOnly, Authorized},
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[get("/audit-events")]
+async fn get_audit_events(
+    _admin: Authorized<AdminOnly>,
+    pool: Data<DbPool>,
+    query: Query<AuditEventQuery>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    let mut sql_query = audit_events::table.into_boxed();
+    if let Some(user_id) = query.user_id {
+        sql_query = sql_query.filter(
+            audit_events::actor_id
+                .eq(user_id)
+                .or(audit_events::subject_id.eq(user_id)),
+        );
+    }
+    if let Some(event_type) = query.event_type {
+        sql_query = sql_query.filter(audit_events::event_type.eq(event_type.as_str()));
+    }
+    if let Some(from) = query.from {
+        sql_query = sql_query.filter(audit_events::created_at.ge(from.naive_utc()));
+    }
+    if let Some(to) = query.to {
+        sql_query = sql_query.filter(audit_events::created_at.lt(to.naive_utc()));
+    }
+    if let Some(before_id) = query.before_id {
+        sql_query = sql_query.filter(audit_events::id.lt(before_id));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let result = sql_query
+        .order(audit_events::id.desc())
+        .limit(limit)
+        .select(AuditEvent::as_select())
+        .load(&mut conn);
+
+    match result {
+        Ok(events) => {
+            // A full page may be followed by more, fetch them with `before_id`
+            let next_before_id = if events.len() as i64 == limit {
+                events.last().map(|event| event.id)
+            } else {
+                None
+            };
+            HttpResponse::Ok().json(json!({
+                "events": events,
+                "next_before_id": next_before_id,
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+}
+
+pub fn configure_audit_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_audit_events);
+}
@@ -1,28 +1,75 @@
-use actix_jwt_auth_middleware::TokenSigner;
 use actix_web::{
-    HttpResponse,
-    cookie::SameSite,
+    HttpRequest, HttpResponse, ResponseError,
+    http::header,
     post,
     web::{self},
 };
 use api::{
-    models::user::{LoginRequest, RegisterUser, User, UserJWT},
+    models::{
+        audit::AuditEventType,
+        mfa::{MfaLoginRequest, MfaPendingJWT},
+        notification::{NotificationKind, NotificationMessage},
+        user::{
+            ForgotPasswordRequest, LoginRequest, PasswordCheck, PasswordlessLoginRequest,
+            PasswordlessRequest, RefreshTokenRequest, RegisterUser, ResetPasswordRequest,
+            SessionMode, User, VerificationChannel, VerifyConfirmRequest, VerifyRequest,
+        },
+    },
     schema::users::{self, email, table},
 };
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
-use diesel::prelude::*;
-use jwt_compact::alg::Ed25519;
-use password_hash::{SaltString, rand_core::OsRng};
-use validator::Validate;
+use chrono::Utc;
+use collection::operations::{password_policy::PasswordPolicy, phone::PhoneNumbers, validation};
+use diesel::{dsl::now, prelude::*, result::DatabaseErrorInformation};
+use serde_json::json;
+use validator::{ValidateArgs, ValidationErrors};
 
-use crate::DbPool;
+use crate::{
+    DbPool,
+    actix::{
+        audit,
+        auth::{AuthError, CookieSettings, refresh_session, session_tokens},
+        csrf::csrf_cookie,
+    },
+    common::{
+        erasure,
+        login_throttle::LoginThrottle,
+        mfa::Mfa,
+        notifications::Notifier,
+        password_reset::PasswordResets,
+        passwordless::{PasswordlessLogins, Redemption},
+        passwords::{PasswordHashing, Verification},
+        refresh_tokens::RefreshFamilies,
+        revocation::RevocationList,
+        sessions,
+        tokens::{TokenIssuer, random_id},
+        verification::{Confirmation, LoginVerification, OtpVerifier},
+    },
+};
 
 #[post("/login")]
+#[allow(clippy::too_many_arguments)]
 async fn login(
+    req: HttpRequest,
     db: web::Data<DbPool>,
     body: web::Json<LoginRequest>,
-    token_signer: web::Data<TokenSigner<UserJWT, Ed25519>>,
+    tokens: web::Data<TokenIssuer>,
+    families: web::Data<RefreshFamilies>,
+    cookies: web::Data<CookieSettings>,
+    verification: web::Data<LoginVerification>,
+    throttle: web::Data<LoginThrottle>,
+    notifier: web::Data<Notifier>,
+    passwords: web::Data<PasswordHashing>,
+    mfa: web::Data<Mfa>,
 ) -> HttpResponse {
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    match throttle.locked_for(&body.email, ip).await {
+        Ok(Some(secs)) => return locked_out(secs),
+        Ok(None) => (),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"));
+        }
+    }
+
     let mut conn = match db.get() {
         Ok(conn) => conn,
         Err(_) => return HttpResponse::InternalServerError().body("DB connection failed"),
@@ -35,67 +82,530 @@ async fn login(
         .optional();
 
     let user = match result {
-        Ok(Some(u)) => u,
-        Ok(_) => return HttpResponse::Unauthorized().body("Invalid email or password"),
+        Ok(u) => u,
         Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
     };
-    let is_password_correct = if let Ok(parsed_hash) = PasswordHash::new(&user.password) {
-        Argon2::default()
-            .verify_password(body.password.as_bytes(), &parsed_hash)
-            .is_ok()
-    } else {
-        false // Invalid stored hash format
+    let verification_result = match &user {
+        Some(user) => passwords.verify(&body.password, &user.password),
+        None => Verification::Invalid,
+    };
+    let user = match user {
+        Some(user) if !matches!(verification_result, Verification::Invalid) => user,
+        user => {
+            // The typed email is left out, it may be someone else's address or even a password
+            // typed into the wrong field, and the log outlives the erasure of accounts
+            audit::record(
+                &mut conn,
+                &req,
+                AuditEventType::LoginFailed,
+                None,
+                user.as_ref().map(|user| user.id),
+                json!({ "method": "password" }),
+            );
+            let lockout = match throttle.record_failure(&body.email, ip).await {
+                Ok(lockout) => lockout,
+                Err(e) => {
+                    return HttpResponse::InternalServerError()
+                        .body(format!("Throttle store error: {e}"));
+                }
+            };
+            let Some(lockout) = lockout else {
+                return HttpResponse::Unauthorized().body("Invalid email or password");
+            };
+            if let Some(user) = user.filter(|_| lockout.account) {
+                audit::record(
+                    &mut conn,
+                    &req,
+                    AuditEventType::AccountLocked,
+                    None,
+                    Some(user.id),
+                    json!({ "retry_after": lockout.retry_after_secs }),
+                );
+                let alert = NotificationMessage {
+                    user_id: user.id,
+                    email: Some(user.email),
+                    phone_number: None,
+                    destinations: vec!["email".to_string()],
+                    kind: NotificationKind::Alert,
+                    message: Some(format!(
+                        "Sign in to your account was locked for {} seconds after repeated \
+                         failed attempts. If this was not you, consider resetting your password.",
+                        lockout.retry_after_secs
+                    )),
+                };
+                // The lockout is in place already, a lost alert must not fail the request
+                if let Err(e) = notifier.send(&alert).await {
+                    log::error!("Failed to send lockout alert to user {}: {e}", user.id);
+                }
+            }
+            return locked_out(lockout.retry_after_secs);
+        }
     };
-    if !is_password_correct {
-        return HttpResponse::Unauthorized().body("Invalid email or password");
+    if let Err(e) = throttle.record_success(&body.email).await {
+        return HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"));
+    }
+    if let Verification::NeedsRehash = verification_result {
+        // Upgrade the stored hash to the current parameters, the login succeeds regardless
+        let rehashed = passwords
+            .hash(&body.password)
+            .map_err(|e| e.to_string())
+            .and_then(|hash| {
+                diesel::update(table.find(user.id))
+                    .set(users::password.eq(hash))
+                    .execute(&mut conn)
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = rehashed {
+            log::error!("Failed to rehash the password of user {}: {e}", user.id);
+        }
+    }
+    if let Err(msg) = verification.check(
+        user.email_verified_at.is_some(),
+        user.phone_verified_at.is_some(),
+    ) {
+        return HttpResponse::Forbidden().body(msg);
     }
 
-    let claims = UserJWT { id: user.id };
+    finish_login(
+        &req, &mut conn, &tokens, &families, &cookies, &mfa, &user, body.mode,
+    )
+    .await
+}
 
-    // Generate cookies
-    let mut access_cookie = match token_signer.create_access_cookie(&claims) {
-        Ok(c) => c,
+#[post("/login/mfa")]
+#[allow(clippy::too_many_arguments)]
+async fn login_mfa(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    body: web::Json<MfaLoginRequest>,
+    tokens: web::Data<TokenIssuer>,
+    families: web::Data<RefreshFamilies>,
+    cookies: web::Data<CookieSettings>,
+    revocations: web::Data<RevocationList>,
+    throttle: web::Data<LoginThrottle>,
+    mfa: web::Data<Mfa>,
+) -> HttpResponse {
+    let claims = match tokens.verify_mfa_token::<MfaPendingJWT>(&body.mfa_token) {
+        Ok(claims) => claims,
+        Err(e) => return AuthError::from(e).error_response(),
+    };
+    let expires_at = claims.expiration.unwrap_or_else(Utc::now);
+    let MfaPendingJWT { id, ver, jti, mode } = claims.custom;
+    match revocations.is_revoked(&jti, id, claims.issued_at).await {
+        Ok(false) => (),
+        Ok(true) => return HttpResponse::Unauthorized().body("Login expired, please log in again"),
         Err(e) => {
-            return HttpResponse::InternalServerError().body(format!("Access token error: {e}"));
+            return HttpResponse::InternalServerError()
+                .body(format!("Revocation store error: {e}"));
         }
+    }
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+    let user = match table.find(id).first::<User>(&mut conn).optional() {
+        Ok(Some(user)) if user.token_version == ver => user,
+        Ok(_) => return HttpResponse::Unauthorized().body("Login expired, please log in again"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
     };
-    let mut refresh_cookie = match token_signer.create_refresh_cookie(&claims) {
-        Ok(c) => c,
+
+    // Codes are guessed against the same counters as passwords
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    match throttle.locked_for(&user.email, ip).await {
+        Ok(Some(secs)) => return locked_out(secs),
+        Ok(None) => (),
         Err(e) => {
-            return HttpResponse::InternalServerError().body(format!("Refresh token error: {e}"));
+            return HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"));
+        }
+    }
+    match mfa.verify(&mut conn, user.id, &body.code) {
+        Ok(true) => (),
+        Ok(false) => {
+            audit::record(
+                &mut conn,
+                &req,
+                AuditEventType::MfaFailed,
+                None,
+                Some(user.id),
+                json!({}),
+            );
+            return match throttle.record_failure(&user.email, ip).await {
+                Ok(Some(lockout)) => locked_out(lockout.retry_after_secs),
+                Ok(None) => HttpResponse::Unauthorized().body("Invalid authentication code"),
+                Err(e) => {
+                    HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"))
+                }
+            };
         }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+    if let Err(e) = throttle.record_success(&user.email).await {
+        return HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"));
+    }
+    // The MFA token is single use
+    if let Err(e) = revocations.revoke(&jti, expires_at).await {
+        return HttpResponse::InternalServerError().body(format!("Revocation store error: {e}"));
+    }
+
+    start_session(&req, &mut conn, &tokens, &families, &cookies, &user, mode).await
+}
+
+#[post("/login/passwordless")]
+async fn request_passwordless_login(
+    pool: web::Data<DbPool>,
+    body: web::Json<PasswordlessRequest>,
+    logins: web::Data<PasswordlessLogins>,
+    notifier: web::Data<Notifier>,
+    phones: web::Data<PhoneNumbers>,
+) -> HttpResponse {
+    let Some(contact) = contact(body.email.as_deref(), body.phone_number.as_deref()) else {
+        return HttpResponse::BadRequest().body("Provide either an email or a phone number");
+    };
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+    let user = match find_by_contact(&mut conn, &phones, contact) {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
     };
-    // Access token cookie configuration
-    access_cookie.set_http_only(true); // ❗ Prevents JavaScript from accessing the cookie (protects against XSS)
-    access_cookie.set_secure(false); // ❗ Ensures the cookie is only sent over HTTPS (protects against MITM)
-    access_cookie.set_same_site(SameSite::Lax); // ❗ Prevents the cookie from being sent in cross-site requests (protects against CSRF)
 
-    // Refresh token cookie configuration
-    refresh_cookie.set_http_only(true);
-    refresh_cookie.set_secure(false);
-    refresh_cookie.set_same_site(SameSite::Lax);
+    // Respond the same way whether or not the account exists
+    let accepted = || {
+        HttpResponse::Accepted().json(serde_json::json!({
+            "message": "If the account exists, a login code has been sent"
+        }))
+    };
+    let Some(user) = user else {
+        return accepted();
+    };
 
-    HttpResponse::Ok()
-        .cookie(access_cookie)
-        .cookie(refresh_cookie)
-        .json(serde_json::json!({
-            "message": "Logged in successfully",
-            "user": {
-                "id": user.id,
-                "email": user.email,
-                "name": user.name,
-                "role": user.role
+    let challenge = match logins.issue(user.id).await {
+        Ok(challenge) => challenge,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Login store error: {e}"));
+        }
+    };
+    let (email_address, phone_number, destination) = match contact {
+        Contact::Email(_) => (Some(user.email), None, "email"),
+        Contact::Phone(_) => (None, user.phone_number, "whatsapp"),
+    };
+    let message = NotificationMessage {
+        user_id: user.id,
+        email: email_address,
+        phone_number,
+        destinations: vec![destination.to_string()],
+        kind: NotificationKind::LoginCode,
+        message: Some(logins.message(&challenge)),
+    };
+    if let Err(e) = notifier.send(&message).await {
+        return HttpResponse::InternalServerError().body(format!("Failed to send code: {e}"));
+    }
+    accepted()
+}
+
+#[post("/login/passwordless/verify")]
+#[allow(clippy::too_many_arguments)]
+async fn passwordless_login(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    body: web::Json<PasswordlessLoginRequest>,
+    logins: web::Data<PasswordlessLogins>,
+    tokens: web::Data<TokenIssuer>,
+    families: web::Data<RefreshFamilies>,
+    cookies: web::Data<CookieSettings>,
+    verification: web::Data<LoginVerification>,
+    throttle: web::Data<LoginThrottle>,
+    mfa: web::Data<Mfa>,
+    phones: web::Data<PhoneNumbers>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+
+    let user_id = if let Some(token) = &body.token {
+        match logins.redeem_link(token).await {
+            Ok(Some(user_id)) => user_id,
+            Ok(None) => {
+                return HttpResponse::Unauthorized().body("Login link expired or already used");
             }
-        }))
+            Err(e) => {
+                return HttpResponse::InternalServerError().body(format!("Login store error: {e}"));
+            }
+        }
+    } else {
+        let (Some(contact), Some(code)) = (
+            contact(body.email.as_deref(), body.phone_number.as_deref()),
+            &body.code,
+        ) else {
+            return HttpResponse::BadRequest()
+                .body("Provide a login link token, or an email or phone number with a code");
+        };
+        let user = match find_by_contact(&mut conn, &phones, contact) {
+            Ok(Some(user)) => user,
+            Ok(None) => return HttpResponse::Unauthorized().body("Invalid login code"),
+            Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+        };
+
+        // Codes are guessed against the same counters as passwords
+        let ip = req.peer_addr().map(|addr| addr.ip());
+        match throttle.locked_for(&user.email, ip).await {
+            Ok(Some(secs)) => return locked_out(secs),
+            Ok(None) => (),
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("Throttle store error: {e}"));
+            }
+        }
+        match logins.redeem_code(user.id, code).await {
+            Ok(Redemption::Valid) => (),
+            Ok(Redemption::Invalid) => {
+                audit::record(
+                    &mut conn,
+                    &req,
+                    AuditEventType::LoginFailed,
+                    None,
+                    Some(user.id),
+                    json!({ "method": "passwordless" }),
+                );
+                return match throttle.record_failure(&user.email, ip).await {
+                    Ok(Some(lockout)) => locked_out(lockout.retry_after_secs),
+                    Ok(None) => HttpResponse::Unauthorized().body("Invalid login code"),
+                    Err(e) => HttpResponse::InternalServerError()
+                        .body(format!("Throttle store error: {e}")),
+                };
+            }
+            Ok(Redemption::Expired) => {
+                return HttpResponse::Unauthorized()
+                    .body("Login code expired, please request a new one");
+            }
+            Ok(Redemption::TooManyAttempts) => {
+                return HttpResponse::Unauthorized()
+                    .body("Too many attempts, please request a new code");
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError().body(format!("Login store error: {e}"));
+            }
+        }
+        if let Err(e) = throttle.record_success(&user.email).await {
+            return HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"));
+        }
+        user.id
+    };
+
+    let user = match table.find(user_id).first::<User>(&mut conn) {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+    if let Err(msg) = verification.check(
+        user.email_verified_at.is_some(),
+        user.phone_verified_at.is_some(),
+    ) {
+        return HttpResponse::Forbidden().body(msg);
+    }
+    finish_login(
+        &req, &mut conn, &tokens, &families, &cookies, &mfa, &user, body.mode,
+    )
+    .await
+}
+
+#[derive(Clone, Copy)]
+enum Contact<'a> {
+    Email(&'a str),
+    Phone(&'a str),
+}
+
+/// The contact a passwordless login identifies the account by, exactly one must be given.
+fn contact<'a>(
+    email_address: Option<&'a str>,
+    phone_number: Option<&'a str>,
+) -> Option<Contact<'a>> {
+    match (email_address, phone_number) {
+        (Some(email_address), None) => Some(Contact::Email(email_address)),
+        (None, Some(phone_number)) => Some(Contact::Phone(phone_number)),
+        _ => None,
+    }
+}
+
+fn find_by_contact(
+    conn: &mut PgConnection,
+    phones: &PhoneNumbers,
+    contact: Contact,
+) -> QueryResult<Option<User>> {
+    match contact {
+        Contact::Email(email_address) => table.filter(email.eq(email_address)).first(conn),
+        // No account has a number that does not parse, and an unverified one may have been set
+        // by someone else
+        Contact::Phone(phone_number) => match phones.normalize(phone_number) {
+            Ok(phone_number) => table
+                .filter(users::phone_number.eq(phone_number))
+                .filter(users::phone_verified_at.is_not_null())
+                .first(conn),
+            Err(_) => return Ok(None),
+        },
+    }
+    .optional()
+}
+
+/// Finish the login of `user` whose first factor checked out, by starting their session or, with
+/// two-factor authentication enabled, handing out the token `/login/mfa` takes with the code.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn finish_login(
+    req: &HttpRequest,
+    conn: &mut PgConnection,
+    tokens: &TokenIssuer,
+    families: &RefreshFamilies,
+    cookies: &CookieSettings,
+    mfa: &Mfa,
+    user: &User,
+    mode: SessionMode,
+) -> HttpResponse {
+    match mfa.is_enabled(conn, user.id) {
+        Ok(false) => start_session(req, conn, tokens, families, cookies, user, mode).await,
+        Ok(true) => {
+            // The password is correct, the session starts once `/login/mfa` checks the code
+            let claims = MfaPendingJWT {
+                id: user.id,
+                ver: user.token_version,
+                jti: random_id(),
+                mode,
+            };
+            match tokens.create_mfa_token(&claims) {
+                Ok(mfa_token) => HttpResponse::Ok().json(serde_json::json!({
+                    "message": "Enter the code from your authenticator app",
+                    "mfa_required": true,
+                    "mfa_token": mfa_token,
+                    "expires_in": tokens.mfa_lifetime().num_seconds()
+                })),
+                Err(e) => HttpResponse::InternalServerError().body(format!("Token error: {e}")),
+            }
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    }
+}
+
+/// Start a new refresh token family for `user`, record it as session of the requesting device
+/// and hand out its tokens the `mode` way.
+async fn start_session(
+    req: &HttpRequest,
+    conn: &mut PgConnection,
+    tokens: &TokenIssuer,
+    families: &RefreshFamilies,
+    cookies: &CookieSettings,
+    user: &User,
+    mode: SessionMode,
+) -> HttpResponse {
+    let (fid, refresh_jti) = match families.start().await {
+        Ok(family) => family,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Session store error: {e}"));
+        }
+    };
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|agent| agent.to_str().ok());
+    let ip = req.peer_addr().map(|addr| addr.ip().to_string());
+    if let Err(e) = sessions::record(conn, user.id, &fid, user_agent, ip) {
+        return HttpResponse::InternalServerError().body(format!("DB error: {}", e));
+    }
+    audit::record_own(
+        conn,
+        req,
+        AuditEventType::LoginSucceeded,
+        user.id,
+        json!({ "mode": mode }),
+    );
+    // Signing in within the grace period keeps the account from being erased
+    if user.deletion_requested_at.is_some()
+        && let Err(e) = erasure::cancel(conn, user.id)
+    {
+        return HttpResponse::InternalServerError().body(format!("DB error: {}", e));
+    }
+    let session = match session_tokens(tokens, user, fid, refresh_jti) {
+        Ok((_, session)) => session,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Token error: {e}")),
+    };
+    let user = serde_json::json!({
+        "id": user.id,
+        "email": user.email,
+        "name": user.name,
+        "role": user.role
+    });
+
+    match mode {
+        SessionMode::Cookie => {
+            let [access_cookie, refresh_cookie] = session.into_cookies(cookies);
+            // A new session gets a new CSRF token, to be echoed on unsafe requests
+            HttpResponse::Ok()
+                .cookie(access_cookie)
+                .cookie(refresh_cookie)
+                .cookie(csrf_cookie(cookies))
+                .json(serde_json::json!({
+                    "message": "Logged in successfully",
+                    "user": user
+                }))
+        }
+        SessionMode::Bearer => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Logged in successfully",
+            "user": user,
+            "token_type": "Bearer",
+            "access_token": session.access_token,
+            "refresh_token": session.refresh_token,
+            "expires_in": tokens.access_lifetime().num_seconds()
+        })),
+    }
+}
+
+#[post("/token/refresh")]
+async fn refresh_token(
+    pool: web::Data<DbPool>,
+    body: web::Json<RefreshTokenRequest>,
+    tokens: web::Data<TokenIssuer>,
+    families: web::Data<RefreshFamilies>,
+    revocations: web::Data<RevocationList>,
+) -> Result<HttpResponse, AuthError> {
+    let (_, session) =
+        refresh_session(&pool, &tokens, &families, &revocations, &body.refresh_token).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "token_type": "Bearer",
+        "access_token": session.access_token,
+        "refresh_token": session.refresh_token,
+        "expires_in": tokens.access_lifetime().num_seconds()
+    })))
 }
 
 #[post("/register")]
-async fn register(pool: web::Data<DbPool>, user: web::Json<RegisterUser>) -> HttpResponse {
+async fn register(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    user: web::Json<RegisterUser>,
+    passwords: web::Data<PasswordHashing>,
+    policy: web::Data<PasswordPolicy>,
+    phones: web::Data<PhoneNumbers>,
+) -> HttpResponse {
+    let mut new_user = user.into_inner();
     // Validate user input
-    match user.validate() {
-        Ok(_) => (),
-        Err(err) => return HttpResponse::BadRequest().json(err),
+    let check = PasswordCheck {
+        policy: &policy,
+        identities: [&new_user.email, &new_user.name],
     };
+    let mut errors = new_user
+        .validate_with_args(&check)
+        .err()
+        .unwrap_or_default();
+    // Stored in E.164, so `unique_phone` catches the same number written differently
+    match phones.normalize(&new_user.phone_number) {
+        Ok(phone_number) => new_user.phone_number = phone_number,
+        Err(err) => errors.add("phone_number", err),
+    }
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": validation::label_errors("Validation error in json", &errors)
+        }));
+    }
 
     // Get a database connection
     let mut conn = match pool.get() {
@@ -103,36 +613,360 @@ async fn register(pool: web::Data<DbPool>, user: web::Json<RegisterUser>) -> Htt
         Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
     };
 
-    let mut new_user = user.into_inner();
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
-    let hashed_password = argon2
-        .hash_password(new_user.password.as_bytes(), &salt)
+    new_user.password = passwords
+        .hash(&new_user.password)
         .expect("password hashing failed");
-
-    new_user.password = hashed_password.to_string();
     // Insert into database
     match diesel::insert_into(users::table)
         .values(&new_user)
-        .execute(&mut *conn)
+        .returning(users::id)
+        .get_result::<i32>(&mut *conn)
     {
-        Ok(user) => HttpResponse::Created().json(user),
+        Ok(user_id) => {
+            audit::record_own(
+                &mut conn,
+                &req,
+                AuditEventType::Registered,
+                user_id,
+                json!({ "role": new_user.role }),
+            );
+            HttpResponse::Created().json(user_id)
+        }
         Err(diesel::result::Error::DatabaseError(
             diesel::result::DatabaseErrorKind::UniqueViolation,
             err,
-        )) => {
-            let msg = match err.constraint_name() {
-                Some("unique_email") => "email",
-                Some("unique_phone") => "phone number",
-                Some(c) => c,                // fallback to raw constraint name
-                None => "one of the fields", // fallback if not available
-            };
-            HttpResponse::Conflict().body(format!("User with {} already exists", msg))
+        )) => conflict(err.as_ref()),
+        Err(e) => HttpResponse::InternalServerError().body(format!("DB Error: {}", e)),
+    }
+}
+
+#[post("/verify/request")]
+async fn request_verification(
+    pool: web::Data<DbPool>,
+    body: web::Json<VerifyRequest>,
+    verifier: web::Data<OtpVerifier>,
+    notifier: web::Data<Notifier>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+
+    let user = match table
+        .filter(email.eq(&body.email))
+        .first::<User>(&mut conn)
+        .optional()
+    {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+
+    // Respond the same way whether or not the account exists
+    let accepted = || {
+        HttpResponse::Accepted().json(serde_json::json!({
+            "message": "If the account exists, a verification code has been sent"
+        }))
+    };
+    let Some(user) = user else {
+        return accepted();
+    };
+
+    let (verified, message) = match body.channel {
+        VerificationChannel::Email => (
+            user.email_verified_at.is_some(),
+            NotificationMessage {
+                user_id: user.id,
+                email: Some(user.email),
+                phone_number: None,
+                destinations: vec!["email".to_string()],
+                kind: NotificationKind::Otp,
+                message: None,
+            },
+        ),
+        // Accounts created through an OIDC provider may not have a phone number yet
+        VerificationChannel::Phone => (
+            user.phone_verified_at.is_some() || user.phone_number.is_none(),
+            NotificationMessage {
+                user_id: user.id,
+                email: None,
+                phone_number: user.phone_number,
+                destinations: vec!["whatsapp".to_string()],
+                kind: NotificationKind::Otp,
+                message: None,
+            },
+        ),
+    };
+    if verified {
+        return accepted();
+    }
+
+    if let Err(e) = verifier.begin(user.id, body.channel).await {
+        return HttpResponse::InternalServerError().body(format!("Verification store error: {e}"));
+    }
+    if let Err(e) = notifier.send(&message).await {
+        return HttpResponse::InternalServerError().body(format!("Failed to send code: {e}"));
+    }
+    accepted()
+}
+
+#[post("/verify/confirm")]
+async fn confirm_verification(
+    pool: web::Data<DbPool>,
+    body: web::Json<VerifyConfirmRequest>,
+    verifier: web::Data<OtpVerifier>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+
+    let user_id = match table
+        .filter(email.eq(&body.email))
+        .select(users::id)
+        .first::<i32>(&mut conn)
+        .optional()
+    {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired verification code"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+
+    let channel = match verifier.confirm(user_id, &body.code).await {
+        Ok(Confirmation::Verified(channel)) => channel,
+        Ok(Confirmation::Invalid | Confirmation::Expired) => {
+            return HttpResponse::BadRequest().body("Invalid or expired verification code");
+        }
+        Ok(Confirmation::TooManyAttempts) => {
+            return HttpResponse::TooManyRequests()
+                .body("Too many attempts, please request a new code");
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Verification store error: {e}"));
         }
+    };
+
+    let target = diesel::update(table.find(user_id));
+    let (updated, verified) = match channel {
+        VerificationChannel::Email => (
+            target
+                .set(users::email_verified_at.eq(now.nullable()))
+                .execute(&mut conn),
+            "email address",
+        ),
+        VerificationChannel::Phone => (
+            target
+                .set(users::phone_verified_at.eq(now.nullable()))
+                .execute(&mut conn),
+            "phone number",
+        ),
+    };
+    match updated {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": format!("Your {verified} has been verified")
+        })),
         Err(e) => HttpResponse::InternalServerError().body(format!("DB Error: {}", e)),
     }
 }
 
+#[post("/password/forgot")]
+async fn forgot_password(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    body: web::Json<ForgotPasswordRequest>,
+    resets: web::Data<PasswordResets>,
+    notifier: web::Data<Notifier>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+
+    let user = match table
+        .filter(email.eq(&body.email))
+        .first::<User>(&mut conn)
+        .optional()
+    {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+
+    // Respond the same way whether or not the account exists
+    let accepted = || {
+        HttpResponse::Accepted().json(serde_json::json!({
+            "message": "If the account exists, a password reset email has been sent"
+        }))
+    };
+    let Some(user) = user else {
+        return accepted();
+    };
+
+    let token = match resets.issue(user.id).await {
+        Ok(token) => token,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Password reset store error: {e}"));
+        }
+    };
+    audit::record(
+        &mut conn,
+        &req,
+        AuditEventType::PasswordResetRequested,
+        None,
+        Some(user.id),
+        json!({}),
+    );
+    let message = NotificationMessage {
+        user_id: user.id,
+        email: Some(user.email),
+        phone_number: None,
+        destinations: vec!["email".to_string()],
+        kind: NotificationKind::PasswordReset,
+        message: Some(resets.message(&token)),
+    };
+    if let Err(e) = notifier.send(&message).await {
+        return HttpResponse::InternalServerError().body(format!("Failed to send email: {e}"));
+    }
+    accepted()
+}
+
+#[post("/password/reset")]
+async fn reset_password(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    body: web::Json<ResetPasswordRequest>,
+    resets: web::Data<PasswordResets>,
+    revocations: web::Data<RevocationList>,
+    passwords: web::Data<PasswordHashing>,
+    policy: web::Data<PasswordPolicy>,
+) -> HttpResponse {
+    let user_id = match resets.peek(&body.token).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired reset token"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Password reset store error: {e}"));
+        }
+    };
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+    let (user_email, user_name) = match table
+        .find(user_id)
+        .select((users::email, users::name))
+        .first::<(String, String)>(&mut conn)
+        .optional()
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return HttpResponse::BadRequest().body("Invalid or expired reset token"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+    // Validate before redeeming, so a rejected password does not burn the token
+    if let Some(response) = reject_password(
+        &policy,
+        &body.password,
+        &[&user_email, &user_name],
+        ValidationErrors::new(),
+    ) {
+        return response;
+    }
+    match resets.redeem(&body.token).await {
+        Ok(Some(redeemed)) if redeemed == user_id => (),
+        Ok(_) => return HttpResponse::BadRequest().body("Invalid or expired reset token"),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Password reset store error: {e}"));
+        }
+    }
+    let hashed_password = match passwords.hash(&body.password) {
+        Ok(hash) => hash,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Hashing error: {e}")),
+    };
+
+    // Bumping the token version stops every refresh token, the revocation every access token
+    let updated = diesel::update(table.find(user_id))
+        .set((
+            users::password.eq(hashed_password),
+            users::token_version.eq(users::token_version + 1),
+            users::updated_at.eq(now),
+        ))
+        .execute(&mut conn);
+    match updated {
+        Ok(0) => return HttpResponse::BadRequest().body("Invalid or expired reset token"),
+        Ok(_) => (),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB Error: {}", e)),
+    }
+    let reset_at = Utc::now();
+    if let Err(e) = revocations.revoke_all_before(user_id, reset_at).await {
+        return HttpResponse::InternalServerError().body(format!("Revocation store error: {e}"));
+    }
+    if let Err(e) = sessions::revoke_all_before(&mut conn, user_id, reset_at) {
+        return HttpResponse::InternalServerError().body(format!("DB Error: {}", e));
+    }
+    audit::record(
+        &mut conn,
+        &req,
+        AuditEventType::PasswordReset,
+        None,
+        Some(user_id),
+        json!({}),
+    );
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Password has been reset, please log in again"
+    }))
+}
+
+/// Add the password policy violations to `errors`, returns a response listing all of them if
+/// there are any.
+pub(crate) fn reject_password(
+    policy: &PasswordPolicy,
+    password: &str,
+    identities: &[&str],
+    mut errors: ValidationErrors,
+) -> Option<HttpResponse> {
+    if let Err(err) = policy.validate(password, identities) {
+        errors.add("password", err);
+    }
+    if errors.is_empty() {
+        return None;
+    }
+    Some(HttpResponse::BadRequest().json(serde_json::json!({
+        "error": validation::label_errors("Validation error in json", &errors)
+    })))
+}
+
+/// Response for a violation of the `unique_email` or `unique_phone` constraint of `users`.
+pub(crate) fn conflict(err: &dyn DatabaseErrorInformation) -> HttpResponse {
+    let msg = match err.constraint_name() {
+        Some("unique_email") => "email",
+        Some("unique_phone") => "phone number",
+        Some(c) => c,                // fallback to raw constraint name
+        None => "one of the fields", // fallback if not available
+    };
+    HttpResponse::Conflict().body(format!("User with {} already exists", msg))
+}
+
+pub(crate) fn locked_out(retry_after_secs: u64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after_secs.to_string()))
+        .json(serde_json::json!({
+            "error": "Too many failed login attempts, please try again later",
+            "retry_after": retry_after_secs
+        }))
+}
+
 pub fn config_auth_api(cfg: &mut web::ServiceConfig) {
-    cfg.service(login).service(register);
+    cfg.service(login)
+        .service(login_mfa)
+        .service(request_passwordless_login)
+        .service(passwordless_login)
+        .service(refresh_token)
+        .service(register)
+        .service(request_verification)
+        .service(confirm_verification)
+        .service(forgot_password)
+        .service(reset_password);
 }
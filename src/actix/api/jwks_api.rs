@@ -0,0 +1,12 @@
+use actix_web::{HttpResponse, get, web};
+
+use crate::common::keys::Keyring;
+
+#[get("/.well-known/jwks.json")]
+async fn jwks(keyring: web::Data<Keyring>) -> HttpResponse {
+    HttpResponse::Ok().json(keyring.jwks())
+}
+
+pub fn configure_jwks_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(jwks);
+}
@@ -0,0 +1,251 @@
+use std::net::IpAddr;
+
+use actix_web::{
+    HttpRequest, HttpResponse, delete, post,
+    web::{self, Data, ReqData},
+};
+use api::{
+    models::{
+        audit::AuditEventType,
+        mfa::{NewRecoveryCode, NewUserTotp, TotpCodeRequest, UserTotp},
+        user::UserJWT,
+    },
+    schema::{mfa_recovery_codes, user_totp, users},
+};
+use diesel::{dsl::now, prelude::*};
+use serde_json::json;
+
+use crate::{
+    DbPool,
+    actix::{api::auth_api::locked_out, audit},
+    common::{
+        login_throttle::LoginThrottle,
+        mfa::{Mfa, generate_recovery_codes, recovery_code_hash},
+    },
+};
+
+#[post("/me/mfa/totp")]
+async fn enroll_totp(claims: ReqData<UserJWT>, pool: Data<DbPool>, mfa: Data<Mfa>) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    match mfa.is_enabled(&mut conn, claims.id) {
+        Ok(false) => (),
+        Ok(true) => {
+            return HttpResponse::Conflict()
+                .body("Two-factor authentication is already enabled, disable it first");
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+    let account = match users::table
+        .find(claims.id)
+        .select(users::email)
+        .first::<String>(&mut conn)
+    {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+
+    // A new enrollment replaces one that was never confirmed
+    let (encrypted, secret) = mfa.generate_secret();
+    let stored = diesel::insert_into(user_totp::table)
+        .values(NewUserTotp {
+            user_id: claims.id,
+            secret: encrypted.clone(),
+        })
+        .on_conflict(user_totp::user_id)
+        .do_update()
+        .set((
+            user_totp::secret.eq(encrypted),
+            user_totp::enabled_at.eq(None::<chrono::NaiveDateTime>),
+            user_totp::last_used_step.eq(None::<i64>),
+            user_totp::created_at.eq(now),
+        ))
+        .execute(&mut conn);
+    if let Err(e) = stored {
+        return HttpResponse::InternalServerError().body(format!("Database error: {e}"));
+    }
+
+    HttpResponse::Ok().json(json!({
+        "message": "Add the secret to your authenticator app, then confirm it with a code",
+        "secret": secret,
+        "provisioning_uri": mfa.provisioning_uri(&account, &secret),
+    }))
+}
+
+#[post("/me/mfa/totp/confirm")]
+async fn confirm_totp(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    mfa: Data<Mfa>,
+    throttle: Data<LoginThrottle>,
+    body: web::Json<TotpCodeRequest>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    let email = match locked_email(&mut conn, &throttle, claims.id, ip).await {
+        Ok(email) => email,
+        Err(response) => return response,
+    };
+    let pending = user_totp::table
+        .find(claims.id)
+        .filter(user_totp::enabled_at.is_null())
+        .first::<UserTotp>(&mut conn)
+        .optional();
+    let totp = match pending {
+        Ok(Some(totp)) => totp,
+        Ok(None) => return HttpResponse::NotFound().body("No pending two-factor enrollment"),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+    let Some(step) = mfa.verify_code(&totp, &body.code) else {
+        return wrong_code(&throttle, &email, ip).await;
+    };
+    if let Err(e) = throttle.record_success(&email).await {
+        return HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"));
+    }
+
+    let codes = generate_recovery_codes();
+    let enabled = conn.transaction(|conn| {
+        diesel::update(user_totp::table.find(claims.id))
+            .set((
+                user_totp::enabled_at.eq(now.nullable()),
+                user_totp::last_used_step.eq(step),
+            ))
+            .execute(conn)?;
+        diesel::delete(mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(claims.id)))
+            .execute(conn)?;
+        diesel::insert_into(mfa_recovery_codes::table)
+            .values(
+                codes
+                    .iter()
+                    .map(|code| NewRecoveryCode {
+                        user_id: claims.id,
+                        code_hash: recovery_code_hash(code),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)
+    });
+
+    match enabled {
+        Ok(_) => {
+            audit::record_own(
+                &mut conn,
+                &req,
+                AuditEventType::MfaEnabled,
+                claims.id,
+                json!({}),
+            );
+            HttpResponse::Ok().json(json!({
+                "message": "Two-factor authentication enabled, store the recovery codes safely",
+                "recovery_codes": codes,
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+}
+
+#[delete("/me/mfa/totp")]
+async fn disable_totp(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    mfa: Data<Mfa>,
+    throttle: Data<LoginThrottle>,
+    body: web::Json<TotpCodeRequest>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    // Require a second factor, a stolen session alone must not be able to remove it
+    let ip = req.peer_addr().map(|addr| addr.ip());
+    let email = match locked_email(&mut conn, &throttle, claims.id, ip).await {
+        Ok(email) => email,
+        Err(response) => return response,
+    };
+    match mfa.verify(&mut conn, claims.id, &body.code) {
+        Ok(true) => (),
+        Ok(false) => return wrong_code(&throttle, &email, ip).await,
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+    if let Err(e) = throttle.record_success(&email).await {
+        return HttpResponse::InternalServerError().body(format!("Throttle store error: {e}"));
+    }
+
+    let disabled = conn.transaction(|conn| {
+        diesel::delete(mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(claims.id)))
+            .execute(conn)?;
+        diesel::delete(user_totp::table.find(claims.id)).execute(conn)
+    });
+    match disabled {
+        Ok(_) => {
+            audit::record_own(
+                &mut conn,
+                &req,
+                AuditEventType::MfaDisabled,
+                claims.id,
+                json!({}),
+            );
+            HttpResponse::Ok().json(json!({ "message": "Two-factor authentication disabled" }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    }
+}
+
+/// Email of the user `user_id`, the key codes are throttled by, or the response to send when
+/// wrong codes locked it out.
+///
+/// Codes are guessed against the same counters as passwords, a stolen session must not be able
+/// to try them faster than the login can.
+async fn locked_email(
+    conn: &mut PgConnection,
+    throttle: &LoginThrottle,
+    user_id: i32,
+    ip: Option<IpAddr>,
+) -> Result<String, HttpResponse> {
+    let email = users::table
+        .find(user_id)
+        .select(users::email)
+        .first::<String>(conn)
+        .map_err(|e| HttpResponse::InternalServerError().body(format!("Database error: {e}")))?;
+    match throttle.locked_for(&email, ip).await {
+        Ok(Some(secs)) => Err(locked_out(secs)),
+        Ok(None) => Ok(email),
+        Err(e) => {
+            Err(HttpResponse::InternalServerError().body(format!("Throttle store error: {e}")))
+        }
+    }
+}
+
+/// Count a wrong code like a failed login, returns the response to send.
+async fn wrong_code(throttle: &LoginThrottle, email: &str, ip: Option<IpAddr>) -> HttpResponse {
+    match throttle.record_failure(email, ip).await {
+        Ok(Some(lockout)) => locked_out(lockout.retry_after_secs),
+        Ok(None) => HttpResponse::BadRequest().body("Invalid authentication code"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Throttle store error: {e}")),
+    }
+}
+
+pub fn configure_mfa_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(enroll_totp);
+    cfg.service(confirm_totp);
+    cfg.service(disable_totp);
+}
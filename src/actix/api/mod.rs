@@ -1,3 +1,10 @@
+pub mod account_api;
+pub mod api_keys_api;
+pub mod audit_api;
 pub mod auth_api;
 pub mod health_check_api;
+pub mod jwks_api;
+pub mod mfa_api;
+pub mod oidc_api;
+pub mod partner_api;
 pub mod users_api;
@@ -0,0 +1,521 @@
+use actix_web::{
+    HttpRequest, HttpResponse,
+    cookie::time::Duration,
+    get,
+    http::header,
+    web::{self, Data},
+};
+use api::{
+    models::{
+        oidc::{NewOidcUser, NewUserIdentity, OidcCallback},
+        user::{SessionMode, User, UserRole},
+    },
+    schema::{user_identities, users},
+};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::{
+    DbPool,
+    actix::{api::auth_api::finish_login, auth::CookieSettings},
+    common::{
+        mfa::Mfa,
+        oidc::{Oidc, OidcIdentity, STATE_LIFETIME_SECS},
+        passwords::PasswordHashing,
+        refresh_tokens::RefreshFamilies,
+        tokens::{TokenIssuer, random_id},
+        verification::LoginVerification,
+    },
+};
+
+/// Cookie binding a sign in to the browser that started it, so a callback URL planted by
+/// someone else cannot log the victim into the attacker's account.
+const OIDC_STATE_COOKIE: &str = "oidc_state";
+
+#[get("/oidc/{provider}/login")]
+async fn oidc_login(
+    provider: web::Path<String>,
+    oidc: Data<Oidc>,
+    cookies: Data<CookieSettings>,
+) -> HttpResponse {
+    match oidc.begin(&provider).await {
+        Ok(Some(started)) => {
+            let mut cookie = cookies.token_cookie(OIDC_STATE_COOKIE, started.state);
+            cookie.set_max_age(Duration::seconds(STATE_LIFETIME_SECS as i64));
+            HttpResponse::Found()
+                .insert_header((header::LOCATION, started.url))
+                .cookie(cookie)
+                .finish()
+        }
+        Ok(None) => HttpResponse::NotFound().body("Unknown sign in provider"),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Sign in store error: {e}")),
+    }
+}
+
+#[get("/oidc/{provider}/callback")]
+#[allow(clippy::too_many_arguments)]
+async fn oidc_callback(
+    req: HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<OidcCallback>,
+    pool: Data<DbPool>,
+    oidc: Data<Oidc>,
+    passwords: Data<PasswordHashing>,
+    tokens: Data<TokenIssuer>,
+    families: Data<RefreshFamilies>,
+    cookies: Data<CookieSettings>,
+    verification: Data<LoginVerification>,
+    mfa: Data<Mfa>,
+) -> HttpResponse {
+    if let Some(error) = &query.error {
+        return HttpResponse::BadRequest().body(format!("Sign in was not completed: {error}"));
+    }
+    let (Some(code), Some(state)) = (&query.code, &query.state) else {
+        return HttpResponse::BadRequest().body("Missing code or state");
+    };
+    // Lax cookies are sent on the top-level redirect back from the provider
+    if req
+        .cookie(OIDC_STATE_COOKIE)
+        .is_none_or(|cookie| cookie.value() != state)
+    {
+        return HttpResponse::BadRequest().body("Sign in was started in another browser");
+    }
+    let pending = match oidc.finish(state).await {
+        Ok(Some(pending)) if pending.provider == *provider => pending,
+        Ok(_) => return HttpResponse::BadRequest().body("Sign in expired, please try again"),
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Sign in store error: {e}"));
+        }
+    };
+    let Some(idp) = oidc.provider(&provider) else {
+        return HttpResponse::NotFound().body("Unknown sign in provider");
+    };
+    let identity = match idp
+        .exchange_code(code, &pending.code_verifier, &oidc.redirect_uri(&provider))
+        .await
+    {
+        Ok(identity) => identity,
+        Err(e) => return HttpResponse::BadGateway().body(format!("Sign in failed: {e}")),
+    };
+
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return HttpResponse::InternalServerError().body("Failed to get DB connection"),
+    };
+    let user = match linked_user(&mut conn, &provider, &identity, &passwords) {
+        Ok(Linked::User(user)) => *user,
+        Ok(Linked::UnverifiedIdentity) => {
+            return HttpResponse::Forbidden()
+                .body("The provider did not confirm your email address");
+        }
+        Ok(Linked::UnverifiedAccount) => {
+            return HttpResponse::Conflict().body(
+                "An account with this email address exists but never verified it. Sign in to \
+                 it, resetting its password if needed, and verify the email address first",
+            );
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("DB error: {}", e)),
+    };
+    if let Err(msg) = verification.check(
+        user.email_verified_at.is_some(),
+        user.phone_verified_at.is_some(),
+    ) {
+        return HttpResponse::Forbidden().body(msg);
+    }
+
+    // Browsers follow the redirect here, so the session always goes into cookies
+    let mut response = finish_login(
+        &req,
+        &mut conn,
+        &tokens,
+        &families,
+        &cookies,
+        &mfa,
+        &user,
+        SessionMode::Cookie,
+    )
+    .await;
+    match response.add_cookie(&cookies.removal_cookie(OIDC_STATE_COOKIE)) {
+        Ok(()) => response,
+        Err(e) => HttpResponse::InternalServerError().body(format!("Cookie error: {e}")),
+    }
+}
+
+/// Account a sign in at a provider resolves to.
+#[derive(Debug)]
+enum Linked {
+    User(Box<User>),
+    /// The identity is not linked yet and the provider did not verify its email.
+    UnverifiedIdentity,
+    /// The account with the email never verified it. Whoever registered it may not own the
+    /// address and could still know its password, so it is not linked.
+    UnverifiedAccount,
+}
+
+/// The user signing in as `identity`, linked earlier or now by an email verified on both sides,
+/// or a new customer.
+fn linked_user(
+    conn: &mut PgConnection,
+    provider: &str,
+    identity: &OidcIdentity,
+    passwords: &PasswordHashing,
+) -> Result<Linked, String> {
+    let linked = user_identities::table
+        .inner_join(users::table)
+        .filter(user_identities::provider.eq(provider))
+        .filter(user_identities::subject.eq(&identity.subject))
+        .select(User::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(user) = linked {
+        return Ok(Linked::User(Box::new(user)));
+    }
+
+    // Linking by an unverified email would hand the account to whoever claims the address
+    let Some(email) = identity
+        .email
+        .as_deref()
+        .filter(|_| identity.email_verified)
+    else {
+        return Ok(Linked::UnverifiedIdentity);
+    };
+    let existing = users::table
+        .filter(users::email.eq(email))
+        .select(User::as_select())
+        .first(conn)
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let new_user = match existing {
+        Some(user) if user.email_verified_at.is_none() => return Ok(Linked::UnverifiedAccount),
+        Some(_) => None,
+        None => Some(NewOidcUser {
+            name: identity
+                .name
+                .as_deref()
+                .unwrap_or_else(|| email.split('@').next().unwrap_or(email)),
+            email,
+            role: UserRole::Customer,
+            // Nobody knows the password, a password reset sets a usable one
+            password: passwords.hash(&random_id()).map_err(|e| e.to_string())?,
+            email_verified_at: Utc::now().naive_utc(),
+        }),
+    };
+
+    conn.transaction(|conn| {
+        let user = match (existing, new_user) {
+            (Some(user), _) => user,
+            (None, new_user) => diesel::insert_into(users::table)
+                .values(new_user)
+                .returning(User::as_returning())
+                .get_result(conn)?,
+        };
+        diesel::insert_into(user_identities::table)
+            .values(NewUserIdentity {
+                user_id: user.id,
+                provider,
+                subject: &identity.subject,
+                email,
+            })
+            .execute(conn)?;
+        Ok(Linked::User(Box::new(user)))
+    })
+    .map_err(|e: diesel::result::Error| e.to_string())
+}
+
+pub fn configure_oidc_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(oidc_login);
+    cfg.service(oidc_callback);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use actix_web::{App, cookie::Cookie, http::StatusCode, test};
+    use collection::connections::connections::create_redis_conn;
+    use diesel::r2d2::{self, ConnectionManager, TestCustomizer};
+    use ed25519_compact::KeyPair;
+
+    use crate::{
+        actix::auth::ACCESS_TOKEN_COOKIE,
+        common::{
+            keys::Keyring,
+            oidc::{MockProvider, OidcProvider},
+        },
+    };
+
+    use super::*;
+
+    const PROVIDER: &str = "mock";
+
+    fn database_url() -> String {
+        dotenvy::dotenv().ok();
+        env::var("DATABASE_URL").expect("DATABASE_URL must be set to run the database tests")
+    }
+
+    /// Connection inside a transaction that is never committed.
+    fn connection() -> PgConnection {
+        let mut conn = PgConnection::establish(&database_url()).expect("connect to DATABASE_URL");
+        conn.begin_test_transaction()
+            .expect("begin test transaction");
+        conn
+    }
+
+    /// The sign in routes with `idp` registered as [`PROVIDER`], on a pool whose transactions are
+    /// never committed.
+    async fn oidc_routes(idp: MockProvider) -> (Data<Oidc>, impl FnOnce(&mut web::ServiceConfig)) {
+        let pool: DbPool = r2d2::Pool::builder()
+            .max_size(1)
+            .connection_customizer(Box::new(TestCustomizer))
+            .build(ConnectionManager::new(database_url()))
+            .expect("connect to DATABASE_URL");
+        let redis = create_redis_conn().await;
+        let oidc = Data::new(Oidc::from_env(redis.clone()).with_provider(PROVIDER, idp));
+        let app_oidc = oidc.clone();
+        let routes = move |cfg: &mut web::ServiceConfig| {
+            cfg.app_data(Data::new(pool))
+                .app_data(app_oidc)
+                .app_data(Data::new(PasswordHashing::from_env()))
+                .app_data(Data::new(TokenIssuer::from_env(Keyring::new(
+                    KeyPair::generate(),
+                    vec![],
+                ))))
+                .app_data(Data::new(RefreshFamilies::new(redis, 3600)))
+                .app_data(Data::new(CookieSettings { secure: false }))
+                .app_data(Data::new(LoginVerification::None))
+                .app_data(Data::new(Mfa::new(&[7; 32], "HavenlyPro".to_string())));
+            configure_oidc_api(cfg);
+        };
+        (oidc, routes)
+    }
+
+    /// State of a sign in started at the login route, taken from the cookie it sets.
+    fn started_state(res: &actix_web::dev::ServiceResponse) -> String {
+        assert_eq!(res.status(), StatusCode::FOUND);
+        res.response()
+            .cookies()
+            .find(|cookie| cookie.name() == OIDC_STATE_COOKIE)
+            .expect("login sets the state cookie")
+            .value()
+            .to_string()
+    }
+
+    fn identity(email: &str, email_verified: bool) -> OidcIdentity {
+        OidcIdentity {
+            subject: random_id(),
+            email: Some(email.to_string()),
+            email_verified,
+            name: Some("Mock User".to_string()),
+        }
+    }
+
+    /// Sign in at the mock provider and resolve the account like the callback does.
+    async fn sign_in(conn: &mut PgConnection, identity: OidcIdentity) -> Linked {
+        let idp = MockProvider { identity };
+        let identity = idp
+            .exchange_code("code", "verifier", "https://api.test/oidc/mock/callback")
+            .await
+            .expect("mock provider signs in");
+        linked_user(conn, PROVIDER, &identity, &PasswordHashing::from_env())
+            .expect("resolve the linked user")
+    }
+
+    fn signed_in_user(linked: Linked) -> User {
+        match linked {
+            Linked::User(user) => *user,
+            other => panic!("expected a user, got {other:?}"),
+        }
+    }
+
+    fn insert_user(conn: &mut PgConnection, email: &str, verified: bool) -> User {
+        diesel::insert_into(users::table)
+            .values((
+                users::name.eq("Existing User"),
+                users::email.eq(email),
+                users::role.eq(UserRole::Customer),
+                users::password.eq("not a hash"),
+                users::email_verified_at.eq(verified.then(|| Utc::now().naive_utc())),
+            ))
+            .returning(User::as_returning())
+            .get_result(conn)
+            .expect("insert user")
+    }
+
+    fn identity_count(conn: &mut PgConnection, user_id: i32) -> i64 {
+        user_identities::table
+            .filter(user_identities::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .expect("count identities")
+    }
+
+    fn new_email() -> String {
+        format!("{}@oidc.test", random_id().to_lowercase())
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs DATABASE_URL with the migrations applied"]
+    async fn creates_verified_customer_for_new_email() {
+        let mut conn = connection();
+        let email = new_email();
+
+        let user = signed_in_user(sign_in(&mut conn, identity(&email, true)).await);
+        assert_eq!(user.email, email);
+        assert_eq!(user.name, "Mock User");
+        assert_eq!(user.role, UserRole::Customer);
+        assert!(user.email_verified_at.is_some());
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs DATABASE_URL with the migrations applied"]
+    async fn links_existing_account_by_verified_email() {
+        let mut conn = connection();
+        let email = new_email();
+        let existing = insert_user(&mut conn, &email, true);
+
+        let user = signed_in_user(sign_in(&mut conn, identity(&email, true)).await);
+        assert_eq!(user.id, existing.id);
+        assert_eq!(identity_count(&mut conn, existing.id), 1);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs DATABASE_URL with the migrations applied"]
+    async fn refuses_to_link_unverified_account() {
+        let mut conn = connection();
+        let email = new_email();
+        // Registered by someone who may not own the address and knows the password
+        let existing = insert_user(&mut conn, &email, false);
+
+        let linked = sign_in(&mut conn, identity(&email, true)).await;
+        assert!(matches!(linked, Linked::UnverifiedAccount), "{linked:?}");
+        assert_eq!(identity_count(&mut conn, existing.id), 0);
+        let unchanged: User = users::table.find(existing.id).first(&mut conn).unwrap();
+        assert!(unchanged.email_verified_at.is_none());
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs DATABASE_URL with the migrations applied"]
+    async fn matches_linked_identity_after_email_change() {
+        let mut conn = connection();
+        let first = identity(&new_email(), true);
+        let linked = signed_in_user(sign_in(&mut conn, first.clone()).await);
+
+        // The provider no longer vouches for any email, the subject alone identifies the account
+        let again = OidcIdentity {
+            email: Some(new_email()),
+            email_verified: false,
+            ..first
+        };
+        let user = signed_in_user(sign_in(&mut conn, again).await);
+        assert_eq!(user.id, linked.id);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs DATABASE_URL with the migrations applied"]
+    async fn refuses_unverified_email() {
+        let mut conn = connection();
+        let email = new_email();
+        let existing = insert_user(&mut conn, &email, true);
+
+        let linked = sign_in(&mut conn, identity(&email, false)).await;
+        assert!(matches!(linked, Linked::UnverifiedIdentity), "{linked:?}");
+        assert_eq!(identity_count(&mut conn, existing.id), 0);
+
+        let unknown = new_email();
+        let linked = sign_in(&mut conn, identity(&unknown, false)).await;
+        assert!(matches!(linked, Linked::UnverifiedIdentity), "{linked:?}");
+        let created: i64 = users::table
+            .filter(users::email.eq(&unknown))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(created, 0);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs DATABASE_URL with the migrations applied and REDIS_URL"]
+    async fn callback_logs_in_the_browser_that_started_the_sign_in() {
+        let email = new_email();
+        let (_, routes) = oidc_routes(MockProvider {
+            identity: identity(&email, true),
+        })
+        .await;
+        let app = test::init_service(App::new().configure(routes)).await;
+
+        let login = test::TestRequest::get()
+            .uri(&format!("/oidc/{PROVIDER}/login"))
+            .to_request();
+        let state = started_state(&test::call_service(&app, login).await);
+
+        let callback = test::TestRequest::get()
+            .uri(&format!(
+                "/oidc/{PROVIDER}/callback?code=code&state={state}"
+            ))
+            .cookie(Cookie::new(OIDC_STATE_COOKIE, state.clone()))
+            .to_request();
+        let res = test::call_service(&app, callback).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookies: Vec<_> = res.response().cookies().collect();
+        assert!(
+            cookies
+                .iter()
+                .any(|cookie| cookie.name() == ACCESS_TOKEN_COOKIE && !cookie.value().is_empty())
+        );
+        assert!(
+            cookies
+                .iter()
+                .any(|cookie| cookie.name() == OIDC_STATE_COOKIE && cookie.value().is_empty())
+        );
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["user"]["email"], email.as_str());
+
+        // The state is single use
+        let replay = test::TestRequest::get()
+            .uri(&format!(
+                "/oidc/{PROVIDER}/callback?code=code&state={state}"
+            ))
+            .cookie(Cookie::new(OIDC_STATE_COOKIE, state))
+            .to_request();
+        let res = test::call_service(&app, replay).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs DATABASE_URL with the migrations applied and REDIS_URL"]
+    async fn callback_refuses_another_browser_and_failed_sign_ins() {
+        let (oidc, routes) = oidc_routes(MockProvider {
+            identity: identity(&new_email(), true),
+        })
+        .await;
+        let app = test::init_service(App::new().configure(routes)).await;
+
+        let login = test::TestRequest::get()
+            .uri(&format!("/oidc/{PROVIDER}/login"))
+            .to_request();
+        let state = started_state(&test::call_service(&app, login).await);
+
+        // A callback URL planted in another browser, which has no or another state cookie
+        for cookie in [None, Some(Cookie::new(OIDC_STATE_COOKIE, random_id()))] {
+            let mut callback = test::TestRequest::get().uri(&format!(
+                "/oidc/{PROVIDER}/callback?code=code&state={state}"
+            ));
+            if let Some(cookie) = cookie {
+                callback = callback.cookie(cookie);
+            }
+            let res = test::call_service(&app, callback.to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+
+        // The refused callbacks left the sign in pending, so it reaches the provider
+        let callback = test::TestRequest::get()
+            .uri(&format!(
+                "/oidc/{PROVIDER}/callback?code=denied&state={state}"
+            ))
+            .cookie(Cookie::new(OIDC_STATE_COOKIE, state.clone()))
+            .to_request();
+        let res = test::call_service(&app, callback).await;
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+        assert!(oidc.finish(&state).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,57 @@
+use actix_web::{
+    HttpResponse, get,
+    web::{self, Data, Query},
+};
+use api::models::common::{Pagination, RawJsonService};
+use diesel::{prelude::*, sql_types::Integer};
+use serde_json::{Value, json};
+
+use crate::{
+    DbPool,
+    actix::api_key::{ApiKeyAuth, ServicesRead},
+};
+
+#[get("/services")]
+async fn get_services(
+    _key: ApiKeyAuth<ServicesRead>,
+    pool: Data<DbPool>,
+    query: Query<Pagination>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    let result: Result<Vec<RawJsonService>, _> = diesel::sql_query(
+        "SELECT row_to_json(s) as service FROM (
+            SELECT id, professional_id, category, description, base_price, created_at, updated_at
+            FROM services ORDER BY id LIMIT $1 OFFSET $2
+        ) s",
+    )
+    .bind::<Integer, _>(query.limit.unwrap_or(10))
+    .bind::<Integer, _>(query.off_set.unwrap_or(0))
+    .get_results(&mut conn);
+
+    match result {
+        Ok(rows) => {
+            let parsed: Result<Vec<Value>, _> = rows
+                .into_iter()
+                .map(|r| serde_json::from_str::<Value>(&r.service))
+                .collect();
+            match parsed {
+                Ok(services) => HttpResponse::Ok().json(json!({ "services": services })),
+                Err(e) => {
+                    HttpResponse::InternalServerError().body(format!("JSON parse error: {e}"))
+                }
+            }
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {:?}", e)),
+    }
+}
+
+pub fn configure_partner_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_services);
+}
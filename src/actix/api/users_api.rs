@@ -1,20 +1,79 @@
 use actix_web::{
-    HttpResponse,
-    cookie::{Cookie, time::Duration},
-    get,
-    web::{self, Data, Path, Query},
+    HttpRequest, HttpResponse, delete, get, patch, post,
+    web::{self, Data, Path, Query, ReqData},
 };
-use api::models::{common::FieldSelection, user::RawJsonUser};
-use diesel::{
-    prelude::*,
-    sql_types::{Integer, Text},
+use api::{
+    models::{
+        audit::AuditEventType,
+        common::{FieldSelection, RawJsonItem, RowCount},
+        session::Session,
+        user::{LogoutEverywhereRequest, UpdateUserRequest, User, UserChanges, UserJWT, UserRole},
+    },
+    schema::{user_sessions, users},
 };
+use chrono::Utc;
+use collection::operations::{
+    list_query::{Field, FieldType, Filter, JsonKey, KeyType, ListQuery, QueryError, Resource},
+    pagination::{Cursor, Direction},
+    phone::PhoneNumbers,
+    validation,
+};
+use diesel::prelude::*;
 use serde_json::{Value, from_str, json};
+use validator::{Validate, ValidationErrors};
 
-use crate::DbPool;
+use crate::{
+    DbPool,
+    actix::{
+        api::auth_api::conflict,
+        audit,
+        auth::{ACCESS_TOKEN_COOKIE, AccessTokenValidity, CookieSettings, REFRESH_TOKEN_COOKIE},
+        guards::{AdminOnly, Authorized, OwnerOrAdmin},
+    },
+    common::{
+        refresh_tokens::RefreshFamilies, revocation::RevocationList, sessions, tokens::TokenIssuer,
+    },
+};
+
+// Fields of users that listings may expose, filter and sort by
+const USERS: Resource = Resource {
+    table: "users",
+    fields: &[
+        Field::new("id", FieldType::Integer),
+        Field::new("name", FieldType::Text),
+        Field::new("email", FieldType::Text),
+        Field::new(
+            "role",
+            FieldType::Enum("user_role", &["admin", "customer", "professional"]),
+        ),
+        Field::new("professional_info", FieldType::Jsonb)
+            .nullable()
+            .keys(&[
+                JsonKey::new("skills", KeyType::StringArray),
+                JsonKey::new("languages", KeyType::StringArray),
+                JsonKey::new("certifications", KeyType::StringArray),
+                JsonKey::new("years_of_experience", KeyType::Number),
+            ]),
+        Field::new("phone_number", FieldType::Text).nullable(),
+        Field::new("created_at", FieldType::Timestamp),
+        Field::new("updated_at", FieldType::Timestamp),
+        // Users also see whether their own addresses are verified
+        Field::new("email_verified_at", FieldType::Timestamp)
+            .nullable()
+            .restricted(),
+        Field::new("phone_verified_at", FieldType::Timestamp)
+            .nullable()
+            .restricted(),
+    ],
+};
 
 #[get("")]
-async fn get_users(pool: web::Data<DbPool>, input: web::Query<FieldSelection>) -> HttpResponse {
+async fn get_users(
+    _admin: Authorized<AdminOnly>,
+    pool: web::Data<DbPool>,
+    input: web::Query<FieldSelection>,
+    phones: web::Data<PhoneNumbers>,
+) -> HttpResponse {
     let mut conn = match pool.get() {
         Ok(c) => c,
         Err(err) => {
@@ -23,122 +82,203 @@ async fn get_users(pool: web::Data<DbPool>, input: web::Query<FieldSelection>) -
         }
     };
 
-    let allowed_fields = [
-        "id",
-        "name",
-        "email",
-        "role",
-        "professional_info",
-        "phone_number",
-        "created_at",
-        "updated_at",
-    ];
+    if input.cursor.is_some() && input.off_set.is_some() {
+        return HttpResponse::BadRequest().body("Use either cursor or skip, not both");
+    }
+    let query = match users_query(&input, &phones) {
+        Ok(query) => query,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    let limit = input.limit.unwrap_or(10).max(0);
+    let skip = input.off_set.unwrap_or(0);
 
-    let selected_fields: Vec<&str> = match &input.fields {
-        Some(fields) => fields
-            .split(',')
-            .map(|f| f.trim())
-            .filter(|f| allowed_fields.contains(f))
-            .collect(),
-        None => allowed_fields.to_vec(),
+    let total = if input.total.unwrap_or(false) {
+        match query.build_count().get_result::<RowCount>(&mut *conn) {
+            Ok(count) => Some(count.total),
+            Err(e) => {
+                return HttpResponse::InternalServerError()
+                    .body(format!("Database error: {:?}", e));
+            }
+        }
+    } else {
+        None
     };
 
-    if selected_fields.is_empty() {
-        return HttpResponse::BadRequest().body("No valid fields provided.");
+    // One row more than asked tells whether there is a page beyond this one
+    let result: Result<Vec<RawJsonItem>, _> = query
+        .clone()
+        .page(i64::from(limit) + 1, i64::from(skip))
+        .build()
+        .get_results(&mut *conn);
+    let mut rows = match result {
+        Ok(rows) => rows,
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(format!("Database error: {:?}", e));
+        }
+    };
+    let has_more = rows.len() > limit as usize;
+    rows.truncate(limit as usize);
+    if query.backwards() {
+        rows.reverse();
     }
 
-    // Build dynamic WHERE clauses and params
-    let mut filters = vec![];
-    let mut bind_values: Vec<String> = vec![];
-    let mut param_counter = 1;
+    let sort_by = sort_field(&input);
+    let descending = is_descending(&input);
+    let cursor_at = |row: Option<&RawJsonItem>, direction| {
+        if !query.supports_cursors() {
+            return None;
+        }
+        let row = row?;
+        Some(
+            Cursor {
+                sort_by: sort_by.to_string(),
+                descending,
+                key: row.sort_key.clone()?,
+                id: row.row_id,
+                direction,
+            }
+            .encode(),
+        )
+    };
+    let (has_next, has_prev) = if query.backwards() {
+        (true, has_more)
+    } else {
+        (has_more, input.cursor.is_some() || skip > 0)
+    };
+    let next = has_next
+        .then(|| cursor_at(rows.last(), Direction::Next))
+        .flatten();
+    let prev = has_prev
+        .then(|| cursor_at(rows.first(), Direction::Prev))
+        .flatten();
+
+    let parsed: Result<Vec<Value>, _> = rows
+        .iter()
+        .map(|r| serde_json::from_str::<Value>(&r.item))
+        .collect();
 
-    if let Some(email) = &input.email {
-        filters.push(format!("email ILIKE ${}", param_counter));
-        bind_values.push(format!("%{}%", email));
-        param_counter += 1;
+    match parsed {
+        Ok(users) => {
+            let mut page = json!({ "users": users, "next": next, "prev": prev });
+            if let Some(total) = total {
+                page["total"] = json!(total);
+            }
+            HttpResponse::Ok().json(page)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("JSON parse error: {e}")),
     }
+}
 
+/// The user listing asked for by `input`, without its page.
+fn users_query(
+    input: &FieldSelection,
+    phones: &PhoneNumbers,
+) -> Result<ListQuery<'static>, QueryError> {
+    let projection = USERS.projection(input.fields.as_deref(), false)?;
+    let mut query = ListQuery::new(&USERS, projection)?;
+    if let Some(email) = &input.email {
+        query = query.filter("email", Filter::Ilike(email))?;
+    }
     if let Some(name) = &input.name {
-        filters.push(format!("name ILIKE ${}", param_counter));
-        bind_values.push(format!("%{}%", name));
-        param_counter += 1;
+        query = query.filter("name", Filter::Ilike(name))?;
     }
-
     if let Some(role) = &input.role {
-        filters.push(format!("role = ${}", param_counter));
-        bind_values.push(role.clone());
-        param_counter += 1;
+        query = query.filter("role", Filter::In(role.split(',').collect()))?;
     }
-
     if let Some(phone) = &input.phone_number {
-        filters.push(format!("phone_number ILIKE ${}", param_counter));
-        bind_values.push(format!("%{}%", phone));
+        // Full numbers match exactly however they are written, fragments still match partially
+        query = match phones.normalize(phone) {
+            Ok(phone) => query.filter("phone_number", Filter::Eq(&phone))?,
+            Err(_) => query.filter("phone_number", Filter::Ilike(phone))?,
+        };
+    }
+    query = query.filter(
+        "created_at",
+        Filter::Range {
+            from: input.created_from.as_deref(),
+            to: input.created_to.as_deref(),
+        },
+    )?;
+    // Professionals having all the listed skills, languages and certifications
+    for (key, values) in [
+        ("skills", &input.skills),
+        ("languages", &input.languages),
+        ("certifications", &input.certifications),
+    ] {
+        if let Some(values) = values {
+            query = query.filter_key(
+                "professional_info",
+                key,
+                Filter::Contains(values.split(',').collect()),
+            )?;
+        }
+    }
+    if let Some(years) = &input.min_experience {
+        query = query.filter_key(
+            "professional_info",
+            "years_of_experience",
+            Filter::AtLeast(years),
+        )?;
+    }
+    if let Some(years) = &input.max_experience {
+        query = query.filter_key(
+            "professional_info",
+            "years_of_experience",
+            Filter::AtMost(years),
+        )?;
+    }
+    if let Some(keys) = &input.has_info {
+        for key in keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+            query = query.filter_key("professional_info", key, Filter::Exists)?;
+        }
     }
 
-    let where_clause = if !filters.is_empty() {
-        format!("WHERE {}", filters.join(" AND "))
-    } else {
-        String::new()
-    };
+    query = query.sort(sort_field(input), is_descending(input))?;
+    match input.cursor.as_deref() {
+        Some(cursor) => query.at(&Cursor::decode(cursor).ok_or(QueryError::InvalidCursor)?),
+        None => Ok(query),
+    }
+}
 
-    let sort_field = input
+/// The requested sort field, or `id` when it is not one to sort by.
+fn sort_field(input: &FieldSelection) -> &str {
+    input
         .sort_by
         .as_deref()
-        .filter(|f| allowed_fields.contains(f))
-        .unwrap_or("id");
+        .filter(|f| {
+            USERS
+                .field(f)
+                .is_ok_and(|field| field.sortable && !field.restricted)
+        })
+        .unwrap_or("id")
+}
 
-    let sort_order = input
+fn is_descending(input: &FieldSelection) -> bool {
+    !input
         .order
         .as_deref()
-        .map(|o| o.to_uppercase())
-        .filter(|o| o == "ASC" || o == "DESC")
-        .unwrap_or_else(|| "DESC".to_string());
-
-    let sql = format!(
-        "SELECT row_to_json(u) as user FROM (
-            SELECT {} FROM users {} ORDER BY {} {} LIMIT ${} OFFSET ${}
-        ) u",
-        selected_fields.join(", "),
-        where_clause,      // e.g. "WHERE email ILIKE $1"
-        sort_field,        // validated & safe
-        sort_order,        // e.g. "DESC" or "ASC"
-        param_counter,     // next bind: LIMIT
-        param_counter + 1  // next bind: OFFSET
-    );
-
-    // Create boxed query to handle dynamic bind count
-    let mut query = diesel::sql_query(sql).into_boxed();
-    for value in bind_values {
-        query = query.bind::<Text, _>(value);
-    }
-
-    query = query
-        .bind::<Integer, _>(input.limit.unwrap_or(10))
-        .bind::<Integer, _>(input.off_set.unwrap_or(0));
-
-    // Execute with boxed query
-    let result: Result<Vec<RawJsonUser>, _> = query.get_results(&mut *conn);
-
-    match result {
-        Ok(rows) => {
-            let parsed: Result<Vec<Value>, _> = rows
-                .into_iter()
-                .map(|r| serde_json::from_str::<Value>(&r.user))
-                .collect();
+        .is_some_and(|o| o.eq_ignore_ascii_case("ASC"))
+}
 
-            match parsed {
-                Ok(users) => HttpResponse::Ok().json(json!({ "users": users })),
-                Err(e) => {
-                    HttpResponse::InternalServerError().body(format!("JSON parse error: {e}"))
-                }
-            }
+#[get("/me")]
+async fn get_me(
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    query: Query<FieldSelection>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection with error: {}", err));
         }
-        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {:?}", e)),
-    }
+    };
+    select_user(&mut conn, claims.id, query.fields.as_deref(), true)
 }
 
 #[get("/{user_id}")]
 async fn get_user(
+    _caller: Authorized<OwnerOrAdmin>,
     pool: Data<DbPool>,
     user_id: Path<i32>,
     query: Query<FieldSelection>,
@@ -150,45 +290,35 @@ async fn get_user(
                 .body(format!("Failed to get DB connection with error: {}", err));
         }
     };
-    let uid = user_id.into_inner();
-
-    // Whitelisted fields (safe to expose)
-    let allowed_fields = [
-        "id",
-        "name",
-        "email",
-        "role",
-        "professional_info",
-        "phone_number",
-        "created_at",
-        "updated_at",
-    ];
+    select_user(
+        &mut conn,
+        user_id.into_inner(),
+        query.fields.as_deref(),
+        false,
+    )
+}
 
-    // Determine selected fields
-    let selected_fields: Vec<&str> = match &query.fields {
-        Some(fields) => fields
-            .split(',')
-            .map(|f| f.trim())
-            .filter(|f| allowed_fields.contains(f))
-            .collect(),
-        None => allowed_fields.to_vec(),
+/// Respond with the `fields` of user `uid`, all of them by default, the restricted ones only
+/// `with_restricted`.
+fn select_user(
+    conn: &mut PgConnection,
+    uid: i32,
+    fields: Option<&str>,
+    with_restricted: bool,
+) -> HttpResponse {
+    let query = USERS
+        .projection(fields, with_restricted)
+        .and_then(|projection| ListQuery::new(&USERS, projection))
+        .and_then(|query| query.filter("id", Filter::Eq(&uid.to_string())));
+    let query = match query {
+        Ok(query) => query.page(1, 0).build(),
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
     };
 
-    if selected_fields.is_empty() {
-        return HttpResponse::BadRequest().body("No valid fields provided.");
-    }
-
-    let sql = format!(
-        "SELECT row_to_json(u) as user FROM (SELECT {} FROM users WHERE id = $1) u",
-        selected_fields.join(", ")
-    );
-
-    let result: Result<RawJsonUser, _> = diesel::sql_query(sql)
-        .bind::<Integer, _>(uid)
-        .get_result(&mut *conn);
+    let result: Result<RawJsonItem, _> = query.get_result(conn);
 
     match result {
-        Ok(raw) => match from_str::<Value>(&raw.user) {
+        Ok(raw) => match from_str::<Value>(&raw.item) {
             Ok(user) => HttpResponse::Ok().json(json!({"user": user})),
             Err(_) => HttpResponse::InternalServerError().body("Failed to parse JSON"),
         },
@@ -199,28 +329,309 @@ async fn get_user(
     }
 }
 
-#[get("/logout")]
-async fn logout() -> HttpResponse {
-    let clear_access = Cookie::build("access_token", "")
-        .http_only(true)
-        .secure(true)
-        .max_age(Duration::seconds(0))
-        .finish();
+#[patch("/{user_id}")]
+async fn update_user(
+    req: HttpRequest,
+    caller: Authorized<OwnerOrAdmin>,
+    pool: Data<DbPool>,
+    user_id: Path<i32>,
+    body: web::Json<UpdateUserRequest>,
+    phones: Data<PhoneNumbers>,
+) -> HttpResponse {
+    if let Err(errs) = body.validate() {
+        return HttpResponse::BadRequest().json(json!({
+            "error": validation::label_errors("Validation error in json", &errs)
+        }));
+    }
+    let request = body.into_inner();
+    let mut changes = UserChanges {
+        name: request.name,
+        professional_info: request.professional_info,
+        ..Default::default()
+    };
+    if let Some(phone_number) = &request.phone_number {
+        match phones.normalize(phone_number) {
+            Ok(phone_number) => changes.phone_number = Some(phone_number),
+            Err(err) => {
+                let mut errs = ValidationErrors::new();
+                errs.add("phone_number", err);
+                return HttpResponse::BadRequest().json(json!({
+                    "error": validation::label_errors("Validation error in json", &errs)
+                }));
+            }
+        }
+    }
+    if changes.name.is_none()
+        && changes.phone_number.is_none()
+        && changes.professional_info.is_none()
+    {
+        return HttpResponse::BadRequest().body("No fields to update");
+    }
+
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+    let uid = user_id.into_inner();
+    let user = match users::table.find(uid).first::<User>(&mut conn) {
+        Ok(user) => user,
+        Err(diesel::result::Error::NotFound) => {
+            return HttpResponse::NotFound()
+                .body(format!("User not found with the provided id {}", uid));
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+    if changes.professional_info.is_some() && user.role != UserRole::Professional {
+        return HttpResponse::BadRequest().body("Only professionals have professional info");
+    }
+    if changes.phone_number.is_some() {
+        if changes.phone_number == user.phone_number {
+            changes.phone_number = None;
+        } else {
+            changes.phone_verified_at = Some(None);
+        }
+    }
+
+    let updated = diesel::update(users::table.find(uid))
+        .set((&changes, users::updated_at.eq(diesel::dsl::now)))
+        .returning(User::as_returning())
+        .get_result(&mut conn);
+    let user = match updated {
+        Ok(user) => user,
+        Err(diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            err,
+        )) => return conflict(err.as_ref()),
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+
+    let fields: Vec<&str> = [
+        ("name", changes.name.is_some()),
+        ("phone_number", changes.phone_number.is_some()),
+        ("professional_info", changes.professional_info.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(field, changed)| changed.then_some(field))
+    .collect();
+    audit::record(
+        &mut conn,
+        &req,
+        AuditEventType::ProfileUpdated,
+        Some(caller.id),
+        Some(uid),
+        json!({ "fields": fields }),
+    );
+
+    HttpResponse::Ok().json(json!({
+        "user": {
+            "id": user.id,
+            "name": user.name,
+            "email": user.email,
+            "role": user.role,
+            "professional_info": user.professional_info,
+            "phone_number": user.phone_number,
+            "created_at": user.created_at,
+            "updated_at": user.updated_at,
+        }
+    }))
+}
 
-    let clear_refresh = Cookie::build("refresh_token", "")
-        .http_only(true)
-        .secure(true)
-        .max_age(Duration::seconds(0))
-        .finish();
+#[post("/logout")]
+async fn logout(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    validity: ReqData<AccessTokenValidity>,
+    pool: Data<DbPool>,
+    revocations: Data<RevocationList>,
+    families: Data<RefreshFamilies>,
+    cookies: Data<CookieSettings>,
+) -> HttpResponse {
+    // Revoke the access token and the refresh token family of this session
+    if let Err(e) = revocations.revoke(&claims.jti, validity.expires_at).await {
+        return HttpResponse::InternalServerError().body(format!("Revocation store error: {e}"));
+    }
+    if let Err(e) = families.revoke(&claims.fid).await {
+        return HttpResponse::InternalServerError().body(format!("Session store error: {e}"));
+    }
+    let revoked = pool.get().map_err(|e| e.to_string()).and_then(|mut conn| {
+        sessions::revoke(&mut conn, &claims.fid).map_err(|e| e.to_string())?;
+        audit::record_own(
+            &mut conn,
+            &req,
+            AuditEventType::Logout,
+            claims.id,
+            json!({}),
+        );
+        Ok(())
+    });
+    if let Err(e) = revoked {
+        return HttpResponse::InternalServerError().body(format!("Database error: {e}"));
+    }
 
     HttpResponse::Ok()
-        .cookie(clear_access)
-        .cookie(clear_refresh)
+        .cookie(cookies.removal_cookie(ACCESS_TOKEN_COOKIE))
+        .cookie(cookies.removal_cookie(REFRESH_TOKEN_COOKIE))
         .json(serde_json::json!({ "message": "Logged out successfully" }))
 }
 
+#[post("/logout/everywhere")]
+async fn logout_everywhere(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    validity: ReqData<AccessTokenValidity>,
+    pool: Data<DbPool>,
+    revocations: Data<RevocationList>,
+    cookies: Data<CookieSettings>,
+    body: Option<web::Json<LogoutEverywhereRequest>>,
+) -> HttpResponse {
+    let now = Utc::now();
+    let before = body
+        .and_then(|body| body.before)
+        .map_or(now, |before| before.min(now));
+
+    if let Err(e) = revocations.revoke_all_before(claims.id, before).await {
+        return HttpResponse::InternalServerError().body(format!("Revocation store error: {e}"));
+    }
+    let revoked = pool.get().map_err(|e| e.to_string()).and_then(|mut conn| {
+        sessions::revoke_all_before(&mut conn, claims.id, before).map_err(|e| e.to_string())?;
+        audit::record_own(
+            &mut conn,
+            &req,
+            AuditEventType::LogoutEverywhere,
+            claims.id,
+            json!({ "revoked_before": before }),
+        );
+        Ok(())
+    });
+    if let Err(e) = revoked {
+        return HttpResponse::InternalServerError().body(format!("Database error: {e}"));
+    }
+
+    let mut response = HttpResponse::Ok();
+    // Only drop the cookies when this session is among the revoked ones
+    if validity.issued_at < before {
+        response
+            .cookie(cookies.removal_cookie(ACCESS_TOKEN_COOKIE))
+            .cookie(cookies.removal_cookie(REFRESH_TOKEN_COOKIE));
+    }
+    response.json(serde_json::json!({
+        "message": "Logged out from all sessions",
+        "revoked_before": before,
+    }))
+}
+
+#[get("/me/sessions")]
+async fn get_sessions(
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    tokens: Data<TokenIssuer>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+
+    // Sessions not refreshed within the refresh token lifetime have expired
+    let active_since = (Utc::now() - tokens.refresh_lifetime()).naive_utc();
+    let result = user_sessions::table
+        .filter(user_sessions::user_id.eq(claims.id))
+        .filter(user_sessions::revoked_at.is_null())
+        .filter(user_sessions::last_seen_at.gt(active_since))
+        .order(user_sessions::last_seen_at.desc())
+        .select(Session::as_select())
+        .load(&mut conn);
+
+    match result {
+        Ok(rows) => {
+            let sessions: Vec<Value> = rows
+                .into_iter()
+                .map(|session| {
+                    let current = session.family_id == claims.fid;
+                    let mut session = json!(session);
+                    session["current"] = json!(current);
+                    session
+                })
+                .collect();
+            HttpResponse::Ok().json(json!({ "sessions": sessions }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(format!("Database error: {:?}", e)),
+    }
+}
+
+#[delete("/me/sessions/{session_id}")]
+async fn delete_session(
+    req: HttpRequest,
+    claims: ReqData<UserJWT>,
+    pool: Data<DbPool>,
+    families: Data<RefreshFamilies>,
+    cookies: Data<CookieSettings>,
+    session_id: Path<i32>,
+) -> HttpResponse {
+    let mut conn = match pool.get() {
+        Ok(c) => c,
+        Err(err) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to get DB connection: {}", err));
+        }
+    };
+    let session_id = session_id.into_inner();
+
+    let fid = match user_sessions::table
+        .find(session_id)
+        .filter(user_sessions::user_id.eq(claims.id))
+        .filter(user_sessions::revoked_at.is_null())
+        .select(user_sessions::family_id)
+        .first::<String>(&mut conn)
+    {
+        Ok(fid) => fid,
+        Err(diesel::result::Error::NotFound) => {
+            return HttpResponse::NotFound().body(format!(
+                "Session not found with the provided id {}",
+                session_id
+            ));
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Database error: {e}")),
+    };
+
+    // The refresh token stops working at once, an access token already handed out to the
+    // device stays valid until it expires
+    if let Err(e) = families.revoke(&fid).await {
+        return HttpResponse::InternalServerError().body(format!("Session store error: {e}"));
+    }
+    if let Err(e) = sessions::revoke(&mut conn, &fid) {
+        return HttpResponse::InternalServerError().body(format!("Database error: {e}"));
+    }
+    audit::record_own(
+        &mut conn,
+        &req,
+        AuditEventType::SessionRevoked,
+        claims.id,
+        json!({ "session_id": session_id }),
+    );
+
+    let mut response = HttpResponse::Ok();
+    if fid == claims.fid {
+        response
+            .cookie(cookies.removal_cookie(ACCESS_TOKEN_COOKIE))
+            .cookie(cookies.removal_cookie(REFRESH_TOKEN_COOKIE));
+    }
+    response.json(json!({ "message": "Session revoked" }))
+}
+
 pub fn configure_users_api(cfg: &mut web::ServiceConfig) {
+    // Register fixed paths before `/{user_id}`, which would match them otherwise
+    cfg.service(logout);
+    cfg.service(logout_everywhere);
+    cfg.service(get_sessions);
+    cfg.service(delete_session);
+    cfg.service(get_me);
     cfg.service(get_users);
     cfg.service(get_user);
-    cfg.service(logout);
+    cfg.service(update_user);
 }
@@ -0,0 +1,153 @@
+use std::{
+    future::{Ready, ready},
+    marker::PhantomData,
+    ops::Deref,
+};
+
+use actix_web::{FromRequest, HttpRequest, dev::Payload, web};
+use api::{
+    models::{
+        api_key::{ApiKey, ApiScope},
+        user::UserRole,
+    },
+    schema,
+};
+use chrono::Utc;
+use diesel::{
+    dsl::{IntervalDsl, now},
+    prelude::*,
+};
+
+use crate::{DbPool, actix::auth::AuthError, common::api_keys};
+
+/// Header partner integrations send their API key in.
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Scope an API key needs to be accepted, e.g. `ApiKeyAuth<ServicesRead>`.
+pub trait RequiredScope {
+    const SCOPE: ApiScope;
+}
+
+#[allow(dead_code)] // No booking routes for partners yet
+pub struct BookingsRead;
+
+impl RequiredScope for BookingsRead {
+    const SCOPE: ApiScope = ApiScope::BookingsRead;
+}
+
+#[allow(dead_code)] // No booking routes for partners yet
+pub struct BookingsWrite;
+
+impl RequiredScope for BookingsWrite {
+    const SCOPE: ApiScope = ApiScope::BookingsWrite;
+}
+
+pub struct ServicesRead;
+
+impl RequiredScope for ServicesRead {
+    const SCOPE: ApiScope = ApiScope::ServicesRead;
+}
+
+/// API key of the request, authenticated by the `X-Api-Key` header and holding the scope `S`.
+///
+/// Responds with 401 when the key is missing, unknown, revoked or expired and 403 when it lacks
+/// the scope.
+pub struct ApiKeyAuth<S> {
+    key: ApiKey,
+    scope: PhantomData<S>,
+}
+
+impl<S> Deref for ApiKeyAuth<S> {
+    type Target = ApiKey;
+
+    fn deref(&self) -> &ApiKey {
+        &self.key
+    }
+}
+
+impl<S: RequiredScope> FromRequest for ApiKeyAuth<S> {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(key) = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+        else {
+            return ready(Err(AuthError::Unauthorized(format!(
+                "Missing {API_KEY_HEADER} header"
+            ))));
+        };
+        let Some(pool) = req.app_data::<web::Data<DbPool>>() else {
+            return ready(Err(AuthError::Internal(
+                "Database pool not configured".to_string(),
+            )));
+        };
+        ready(authenticate(pool, key.trim(), S::SCOPE).map(|key| Self {
+            key,
+            scope: PhantomData,
+        }))
+    }
+}
+
+fn authenticate(pool: &DbPool, key: &str, scope: ApiScope) -> Result<ApiKey, AuthError> {
+    let invalid = || AuthError::Unauthorized("Invalid API key".to_string());
+    let prefix = api_keys::prefix(key).ok_or_else(invalid)?;
+
+    let mut conn = pool
+        .get()
+        .map_err(|err| AuthError::Internal(format!("Failed to get DB connection: {err}")))?;
+    let (stored, role) = schema::api_keys::table
+        .inner_join(schema::users::table)
+        .filter(schema::api_keys::prefix.eq(prefix))
+        .select((ApiKey::as_select(), schema::users::role))
+        .first::<(ApiKey, UserRole)>(&mut conn)
+        .optional()
+        .map_err(|err| AuthError::Internal(format!("Database error: {err}")))?
+        .filter(|(stored, _)| stored.key_hash == api_keys::hash(key))
+        .ok_or_else(invalid)?;
+
+    if stored.revoked_at.is_some() {
+        return Err(AuthError::Unauthorized(
+            "API key has been revoked".to_string(),
+        ));
+    }
+    if stored
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    {
+        return Err(AuthError::Unauthorized("API key has expired".to_string()));
+    }
+    // The owner may have lost the role the scope was granted under since
+    if !scope.allowed_for(role)
+        || !stored
+            .scopes
+            .iter()
+            .any(|granted| granted == scope.as_str())
+    {
+        return Err(AuthError::Forbidden(format!(
+            "API key lacks the {} scope",
+            scope.as_str()
+        )));
+    }
+
+    // Keep writes down for busy integrations, a minute is precise enough
+    let touched = diesel::update(
+        schema::api_keys::table.find(stored.id).filter(
+            schema::api_keys::last_used_at
+                .is_null()
+                .or(schema::api_keys::last_used_at.lt((now - 1.minute()).nullable())),
+        ),
+    )
+    .set(schema::api_keys::last_used_at.eq(now.nullable()))
+    .execute(&mut conn);
+    if let Err(err) = touched {
+        log::error!(
+            "Failed to update the last use of API key {}: {err}",
+            stored.id
+        );
+    }
+
+    Ok(stored)
+}
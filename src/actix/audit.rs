@@ -0,0 +1,56 @@
+use actix_web::{HttpRequest, http::header};
+use api::{
+    models::audit::{AuditEventType, NewAuditEvent},
+    schema::audit_events,
+};
+use diesel::prelude::*;
+use serde_json::Value;
+
+/// Append an event to the audit log, with the IP address and user agent of `req`.
+///
+/// `actor_id` is the user who made the request, `subject_id` the user it concerns. A failed write
+/// is logged, the action being audited has happened regardless.
+pub fn record(
+    conn: &mut PgConnection,
+    req: &HttpRequest,
+    event_type: AuditEventType,
+    actor_id: Option<i32>,
+    subject_id: Option<i32>,
+    metadata: Value,
+) {
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|agent| agent.to_str().ok());
+    let inserted = diesel::insert_into(audit_events::table)
+        .values(NewAuditEvent {
+            event_type: event_type.as_str(),
+            actor_id,
+            subject_id,
+            ip_address: req.peer_addr().map(|addr| addr.ip().to_string()),
+            user_agent,
+            metadata,
+        })
+        .execute(conn);
+    if let Err(e) = inserted {
+        log::error!("Failed to record {} audit event: {e}", event_type.as_str());
+    }
+}
+
+/// [`record`] for users acting on their own account.
+pub fn record_own(
+    conn: &mut PgConnection,
+    req: &HttpRequest,
+    event_type: AuditEventType,
+    user_id: i32,
+    metadata: Value,
+) {
+    record(
+        conn,
+        req,
+        event_type,
+        Some(user_id),
+        Some(user_id),
+        metadata,
+    );
+}
@@ -0,0 +1,438 @@
+use std::{
+    env, fmt,
+    future::{Future, Ready, ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    Error, HttpMessage, HttpResponse, ResponseError,
+    body::MessageBody,
+    cookie::{Cookie, SameSite},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    http::{StatusCode, header},
+    web,
+};
+use api::{
+    models::user::{RefreshJWT, User, UserJWT},
+    schema::users,
+};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use jwt_compact::Claims;
+use serde_json::json;
+
+use crate::{
+    DbPool,
+    common::{
+        refresh_tokens::{RefreshFamilies, Rotation},
+        revocation::RevocationList,
+        sessions,
+        tokens::{TokenError, TokenIssuer, random_id},
+    },
+};
+
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
+pub const REFRESH_TOKEN_COOKIE: &str = "refresh_token";
+
+#[derive(Debug)]
+pub enum AuthError {
+    Unauthorized(String),
+    Forbidden(String),
+    Internal(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unauthorized(msg) | AuthError::Forbidden(msg) | AuthError::Internal(msg) => {
+                f.write_str(msg)
+            }
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AuthError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({ "error": self.to_string() }))
+    }
+}
+
+impl From<TokenError> for AuthError {
+    fn from(err: TokenError) -> Self {
+        match err {
+            TokenError::Creation(err) => AuthError::Internal(format!("Token error: {err}")),
+            err => AuthError::Unauthorized(err.to_string()),
+        }
+    }
+}
+
+/// Issue and expiry time of the access token a request was authenticated with.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessTokenValidity {
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// How the session cookies are set.
+#[derive(Debug, Clone, Copy)]
+pub struct CookieSettings {
+    /// Only send the cookies over HTTPS.
+    pub secure: bool,
+}
+
+impl CookieSettings {
+    /// Reads `COOKIE_SECURE`, `true` by default. Set it to `false` to serve over plain HTTP in
+    /// development.
+    pub fn from_env() -> Self {
+        let secure = match env::var("COOKIE_SECURE").as_deref() {
+            Err(_) | Ok("") | Ok("true") => true,
+            Ok("false") => false,
+            Ok(other) => panic!("COOKIE_SECURE must be true or false, got {other}"),
+        };
+        Self { secure }
+    }
+
+    /// Cookie carrying a token.
+    pub fn token_cookie(&self, name: &'static str, token: String) -> Cookie<'static> {
+        Cookie::build(name, token)
+            .path("/")
+            .http_only(true) // ❗ Prevents JavaScript from accessing the cookie (protects against XSS)
+            .secure(self.secure) // ❗ Ensures the cookie is only sent over HTTPS (protects against MITM)
+            .same_site(SameSite::Lax) // ❗ Prevents the cookie from being sent in cross-site requests (protects against CSRF)
+            .finish()
+    }
+
+    /// Cookie telling the client to drop a token cookie.
+    pub fn removal_cookie(&self, name: &'static str) -> Cookie<'static> {
+        let mut cookie = self.token_cookie(name, String::new());
+        cookie.make_removal();
+        cookie
+    }
+}
+
+/// Access and refresh token of a session.
+pub struct SessionTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+}
+
+impl SessionTokens {
+    pub fn into_cookies(self, cookies: &CookieSettings) -> [Cookie<'static>; 2] {
+        [
+            cookies.token_cookie(ACCESS_TOKEN_COOKIE, self.access_token),
+            cookies.token_cookie(REFRESH_TOKEN_COOKIE, self.refresh_token),
+        ]
+    }
+}
+
+/// Issue an access token and the refresh token `refresh_jti` of the family `fid` for `user`.
+pub fn session_tokens(
+    tokens: &TokenIssuer,
+    user: &User,
+    fid: String,
+    refresh_jti: String,
+) -> Result<(UserJWT, SessionTokens), TokenError> {
+    let access = UserJWT {
+        id: user.id,
+        role: user.role,
+        ver: user.token_version,
+        jti: random_id(),
+        fid: fid.clone(),
+    };
+    let refresh = RefreshJWT {
+        id: user.id,
+        ver: user.token_version,
+        jti: refresh_jti,
+        fid,
+    };
+    let session = SessionTokens {
+        access_token: tokens.create_access_token(&access)?,
+        refresh_token: tokens.create_refresh_token(&refresh)?,
+    };
+    Ok((access, session))
+}
+
+/// Rotate the refresh token `refresh_token` and issue a new access token with it.
+///
+/// Revoked refresh tokens are rejected, and so are those issued before the user's
+/// `token_version` was bumped; the new access token carries the current role.
+pub async fn refresh_session(
+    pool: &DbPool,
+    tokens: &TokenIssuer,
+    families: &RefreshFamilies,
+    revocations: &RevocationList,
+    refresh_token: &str,
+) -> Result<(UserJWT, SessionTokens), AuthError> {
+    let claims = tokens.verify_refresh_token::<RefreshJWT>(refresh_token)?;
+    let RefreshJWT { id, ver, jti, fid } = claims.custom;
+    if is_revoked(revocations, &jti, id, claims.issued_at).await? {
+        return Err(AuthError::Unauthorized(
+            "Session has been revoked, please log in again".to_string(),
+        ));
+    }
+
+    let user = find_user(pool, id)?;
+    if user.token_version != ver {
+        families
+            .revoke(&fid)
+            .await
+            .map_err(|err| AuthError::Internal(format!("Session store error: {err}")))?;
+        return Err(AuthError::Unauthorized(
+            "Session is no longer valid, please log in again".to_string(),
+        ));
+    }
+
+    let next_jti = match families.rotate(&fid, &jti).await {
+        Ok(Rotation::Rotated(next_jti)) => next_jti,
+        Ok(Rotation::Reused) => {
+            log::warn!("Refresh token reuse detected for user {id}, revoked token family {fid}");
+            return Err(AuthError::Unauthorized(
+                "Refresh token reuse detected, please log in again".to_string(),
+            ));
+        }
+        Ok(Rotation::Unknown) => {
+            return Err(AuthError::Unauthorized(
+                "Session expired, please log in again".to_string(),
+            ));
+        }
+        Err(err) => return Err(AuthError::Internal(format!("Session store error: {err}"))),
+    };
+
+    if let Err(err) = pool
+        .get()
+        .map_err(|err| err.to_string())
+        .and_then(|mut conn| sessions::touch(&mut conn, &fid).map_err(|err| err.to_string()))
+    {
+        log::error!("Failed to update the last seen time of session {fid}: {err}");
+    }
+
+    Ok(session_tokens(tokens, &user, fid, next_jti)?)
+}
+
+/// Authenticates requests by their `Authorization: Bearer` header or access token cookie and
+/// inserts the [`UserJWT`] claims and [`AccessTokenValidity`] into the request extensions,
+/// extract them with `web::ReqData`.
+///
+/// When the access token cookie is missing or expired, the refresh token cookie is rotated with
+/// [`refresh_session`] and both cookies are replaced on the response. Bearer clients hold their
+/// refresh token themselves and renew through `/token/refresh` instead. Revoked tokens, and those
+/// issued before the user's `token_version` was bumped, are rejected either way.
+pub struct JwtAuth {
+    pool: web::Data<DbPool>,
+    tokens: web::Data<TokenIssuer>,
+    families: web::Data<RefreshFamilies>,
+    revocations: web::Data<RevocationList>,
+    cookies: web::Data<CookieSettings>,
+}
+
+impl JwtAuth {
+    pub fn new(
+        pool: web::Data<DbPool>,
+        tokens: web::Data<TokenIssuer>,
+        families: web::Data<RefreshFamilies>,
+        revocations: web::Data<RevocationList>,
+        cookies: web::Data<CookieSettings>,
+    ) -> Self {
+        Self {
+            pool,
+            tokens,
+            families,
+            revocations,
+            cookies,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for JwtAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = JwtAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(JwtAuthMiddleware {
+            service: Rc::new(service),
+            pool: self.pool.clone(),
+            tokens: self.tokens.clone(),
+            families: self.families.clone(),
+            revocations: self.revocations.clone(),
+            cookies: self.cookies.clone(),
+        }))
+    }
+}
+
+pub struct JwtAuthMiddleware<S> {
+    service: Rc<S>,
+    pool: web::Data<DbPool>,
+    tokens: web::Data<TokenIssuer>,
+    families: web::Data<RefreshFamilies>,
+    revocations: web::Data<RevocationList>,
+    cookies: web::Data<CookieSettings>,
+}
+
+impl<S, B> Service<ServiceRequest> for JwtAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let pool = self.pool.clone();
+        let tokens = self.tokens.clone();
+        let families = self.families.clone();
+        let revocations = self.revocations.clone();
+        let cookies = self.cookies.clone();
+
+        Box::pin(async move {
+            let renewed = authenticate(&req, &pool, &tokens, &families, &revocations).await?;
+            let mut res = service.call(req).await?;
+            for cookie in renewed
+                .into_iter()
+                .flat_map(|session| session.into_cookies(&cookies))
+            {
+                res.response_mut().add_cookie(&cookie)?;
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Returns the tokens to set as cookies when the session had to be renewed.
+async fn authenticate(
+    req: &ServiceRequest,
+    pool: &DbPool,
+    tokens: &TokenIssuer,
+    families: &RefreshFamilies,
+    revocations: &RevocationList,
+) -> Result<Option<SessionTokens>, AuthError> {
+    if let Some(token) = bearer_token(req)? {
+        return match tokens.verify_access_token::<UserJWT>(&token) {
+            Ok(claims) => {
+                accept_access_token(req, pool, revocations, claims).await?;
+                Ok(None)
+            }
+            Err(err) if err.is_expired() => Err(AuthError::Unauthorized(
+                "Access token expired, please refresh it".to_string(),
+            )),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    if let Some(cookie) = req.cookie(ACCESS_TOKEN_COOKIE) {
+        match tokens.verify_access_token::<UserJWT>(cookie.value()) {
+            Ok(claims) => {
+                accept_access_token(req, pool, revocations, claims).await?;
+                return Ok(None);
+            }
+            Err(err) if err.is_expired() => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let Some(cookie) = req.cookie(REFRESH_TOKEN_COOKIE) else {
+        return Err(AuthError::Unauthorized(
+            "No token found, please log in".to_string(),
+        ));
+    };
+    let (claims, session) =
+        refresh_session(pool, tokens, families, revocations, cookie.value()).await?;
+    let now = Utc::now();
+    req.extensions_mut().insert(AccessTokenValidity {
+        issued_at: now,
+        expires_at: now + tokens.access_lifetime(),
+    });
+    req.extensions_mut().insert(claims);
+    Ok(Some(session))
+}
+
+/// The token of an `Authorization: Bearer` header, if the request has one.
+pub(crate) fn bearer_token(req: &ServiceRequest) -> Result<Option<String>, AuthError> {
+    let Some(header) = req.headers().get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    header
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| Some(token.trim().to_string()))
+        .ok_or_else(|| AuthError::Unauthorized("Malformed Authorization header".to_string()))
+}
+
+/// Accept a verified access token unless it was revoked or issued before the user's
+/// `token_version` was bumped, e.g. by a role change or a password reset.
+async fn accept_access_token(
+    req: &ServiceRequest,
+    pool: &DbPool,
+    revocations: &RevocationList,
+    claims: Claims<UserJWT>,
+) -> Result<(), AuthError> {
+    if is_revoked(
+        revocations,
+        &claims.custom.jti,
+        claims.custom.id,
+        claims.issued_at,
+    )
+    .await?
+    {
+        return Err(AuthError::Unauthorized(
+            "Token has been revoked, please log in again".to_string(),
+        ));
+    }
+    // Checked on every request, the role in a token must not outlive a demotion
+    if find_user(pool, claims.custom.id)?.token_version != claims.custom.ver {
+        return Err(AuthError::Unauthorized(
+            "Session is no longer valid, please log in again".to_string(),
+        ));
+    }
+    req.extensions_mut().insert(AccessTokenValidity {
+        issued_at: claims.issued_at.unwrap_or_else(Utc::now),
+        expires_at: claims.expiration.unwrap_or_else(Utc::now),
+    });
+    req.extensions_mut().insert(claims.custom);
+    Ok(())
+}
+
+fn find_user(pool: &DbPool, id: i32) -> Result<User, AuthError> {
+    let mut conn = pool
+        .get()
+        .map_err(|err| AuthError::Internal(format!("Failed to get DB connection: {err}")))?;
+    users::table
+        .find(id)
+        .first::<User>(&mut conn)
+        .optional()
+        .map_err(|err| AuthError::Internal(format!("Database error: {err}")))?
+        .ok_or_else(|| AuthError::Unauthorized("Account no longer exists".to_string()))
+}
+
+async fn is_revoked(
+    revocations: &RevocationList,
+    jti: &str,
+    user_id: i32,
+    issued_at: Option<DateTime<Utc>>,
+) -> Result<bool, AuthError> {
+    revocations
+        .is_revoked(jti, user_id, issued_at)
+        .await
+        .map_err(|err| AuthError::Internal(format!("Revocation store error: {err}")))
+}
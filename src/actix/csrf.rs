@@ -0,0 +1,132 @@
+use std::{
+    future::{Future, Ready, ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    Error,
+    body::MessageBody,
+    cookie::{Cookie, SameSite},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    web,
+};
+
+use crate::{
+    actix::auth::{
+        ACCESS_TOKEN_COOKIE, AuthError, CookieSettings, REFRESH_TOKEN_COOKIE, bearer_token,
+    },
+    common::tokens::random_id,
+};
+
+pub const CSRF_TOKEN_COOKIE: &str = "csrf_token";
+pub const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+
+/// Cookie carrying a new CSRF token, readable by the frontend so it can echo it in the
+/// [`CSRF_TOKEN_HEADER`] header.
+pub fn csrf_cookie(cookies: &CookieSettings) -> Cookie<'static> {
+    Cookie::build(CSRF_TOKEN_COOKIE, random_id())
+        .path("/")
+        .secure(cookies.secure)
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+/// Double-submit CSRF protection for routes authenticated by the session cookies.
+///
+/// `POST`, `PUT`, `PATCH` and `DELETE` requests carrying a session cookie must repeat the value
+/// of the [`CSRF_TOKEN_COOKIE`] cookie in the [`CSRF_TOKEN_HEADER`] header. Another site can
+/// make the browser send the cookies but cannot read them. Requests with a bearer token are left
+/// alone, [`JwtAuth`](crate::actix::auth::JwtAuth) then ignores the cookies and the browser never
+/// sends the token on its own.
+///
+/// Sessions without a CSRF token, e.g. started before it existed, get one on their next safe
+/// request.
+pub struct Csrf {
+    cookies: web::Data<CookieSettings>,
+}
+
+impl Csrf {
+    pub fn new(cookies: web::Data<CookieSettings>) -> Self {
+        Self { cookies }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Csrf
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware {
+            service: Rc::new(service),
+            cookies: self.cookies.clone(),
+        }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+    cookies: web::Data<CookieSettings>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let cookies = self.cookies.clone();
+
+        Box::pin(async move {
+            let cookie_session = is_cookie_session(&req);
+            let issue_token = cookie_session && req.cookie(CSRF_TOKEN_COOKIE).is_none();
+            if cookie_session && !req.method().is_safe() {
+                check_token(&req)?;
+            }
+            let mut res = service.call(req).await?;
+            if issue_token {
+                res.response_mut().add_cookie(&csrf_cookie(&cookies))?;
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Whether the request would be authenticated by the session cookies.
+fn is_cookie_session(req: &ServiceRequest) -> bool {
+    // Any other header, e.g. an API key, is ignored by JwtAuth and must not skip the check
+    if let Ok(Some(_)) = bearer_token(req) {
+        return false;
+    }
+    req.cookie(ACCESS_TOKEN_COOKIE).is_some() || req.cookie(REFRESH_TOKEN_COOKIE).is_some()
+}
+
+fn check_token(req: &ServiceRequest) -> Result<(), AuthError> {
+    let cookie = req.cookie(CSRF_TOKEN_COOKIE);
+    let header = req
+        .headers()
+        .get(CSRF_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match (cookie, header) {
+        (Some(cookie), Some(header)) if !cookie.value().is_empty() && cookie.value() == header => {
+            Ok(())
+        }
+        _ => Err(AuthError::Forbidden(format!(
+            "Missing or invalid {CSRF_TOKEN_HEADER} header"
+        ))),
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    future::{Ready, ready},
+    marker::PhantomData,
+    ops::Deref,
+};
+
+use actix_web::{FromRequest, HttpMessage, HttpRequest, dev::Payload};
+use api::models::user::{UserJWT, UserRole};
+
+use crate::actix::auth::AuthError;
+
+/// Access rule checked against the claims of the authenticated user.
+pub trait Policy {
+    fn allows(claims: &UserJWT, req: &HttpRequest) -> bool;
+}
+
+pub struct AdminOnly;
+
+impl Policy for AdminOnly {
+    fn allows(claims: &UserJWT, _req: &HttpRequest) -> bool {
+        claims.role == UserRole::Admin
+    }
+}
+
+#[allow(dead_code)] // No professional only routes yet
+pub struct ProfessionalOnly;
+
+impl Policy for ProfessionalOnly {
+    fn allows(claims: &UserJWT, _req: &HttpRequest) -> bool {
+        claims.role == UserRole::Professional
+    }
+}
+
+/// Admins, or the user addressed by the `{user_id}` path segment.
+pub struct OwnerOrAdmin;
+
+impl Policy for OwnerOrAdmin {
+    fn allows(claims: &UserJWT, req: &HttpRequest) -> bool {
+        claims.role == UserRole::Admin
+            || req
+                .match_info()
+                .get("user_id")
+                .and_then(|id| id.parse::<i32>().ok())
+                .is_some_and(|id| id == claims.id)
+    }
+}
+
+/// Claims of a user allowed by the policy `P`, e.g. `Authorized<AdminOnly>`.
+///
+/// Must be used behind [`JwtAuth`](crate::actix::auth::JwtAuth). Responds with 401 when the
+/// request is not authenticated and 403 when the policy denies it.
+pub struct Authorized<P> {
+    claims: UserJWT,
+    policy: PhantomData<P>,
+}
+
+impl<P> Deref for Authorized<P> {
+    type Target = UserJWT;
+
+    fn deref(&self) -> &UserJWT {
+        &self.claims
+    }
+}
+
+impl<P: Policy> FromRequest for Authorized<P> {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let Some(claims) = req.extensions().get::<UserJWT>().cloned() else {
+            return ready(Err(AuthError::Unauthorized(
+                "No token found, please log in".to_string(),
+            )));
+        };
+        if !P::allows(&claims, req) {
+            return ready(Err(AuthError::Forbidden(
+                "You are not allowed to access this resource".to_string(),
+            )));
+        }
+        ready(Ok(Self {
+            claims,
+            policy: PhantomData,
+        }))
+    }
+}
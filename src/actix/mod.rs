@@ -1 +1,6 @@
 pub mod api;
+pub mod api_key;
+pub mod audit;
+pub mod auth;
+pub mod csrf;
+pub mod guards;
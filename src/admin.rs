@@ -0,0 +1,97 @@
+use std::{env, io, path::Path};
+
+use api::schema::users;
+use collection::operations::phone::PhoneNumbers;
+use diesel::{
+    prelude::*,
+    result::{DatabaseErrorKind, Error},
+};
+
+use crate::common::{erasure::AccountErasure, keys::generate_key_file};
+
+const USAGE: &str = "usage: havenlyPro keys generate [DIR]
+       havenlyPro accounts erase
+       havenlyPro phones normalize";
+
+/// Run an administrative command given on the command line instead of starting the server.
+pub fn run(args: &[String]) -> io::Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["keys", "generate"] => generate_key(Path::new("keys")),
+        ["keys", "generate", dir] => generate_key(Path::new(dir)),
+        ["accounts", "erase"] => erase_accounts(),
+        ["phones", "normalize"] => normalize_phones(),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
+
+fn generate_key(dir: &Path) -> io::Result<()> {
+    let (kid, path) = generate_key_file(dir)?;
+    println!("Generated signing key {kid} at {}", path.display());
+    println!("To rotate, point JWT_SIGNING_KEY_FILE at the new key and append the previous");
+    println!("key file to JWT_RETIRED_KEY_FILES until tokens signed with it have expired.");
+    Ok(())
+}
+
+/// Erase the accounts whose deletion grace period is over, meant to run periodically, e.g. from
+/// cron.
+fn erase_accounts() -> io::Result<()> {
+    let mut conn = connect()?;
+    let erased = AccountErasure::from_env()
+        .erase_due(&mut conn)
+        .map_err(io::Error::other)?;
+    println!("Erased {erased} accounts");
+    Ok(())
+}
+
+/// Rewrite the stored phone numbers in E.164, which the migration adding the `phone_number_e164`
+/// constraint waits for. Numbers that do not parse or turn out to be duplicates are listed to be
+/// fixed by hand.
+fn normalize_phones() -> io::Result<()> {
+    let mut conn = connect()?;
+    let phones = PhoneNumbers::from_env();
+    let stored = users::table
+        .filter(users::phone_number.is_not_null())
+        .select((users::id, users::phone_number.assume_not_null()))
+        .order(users::id)
+        .load::<(i32, String)>(&mut conn)
+        .map_err(io::Error::other)?;
+
+    let (mut updated, mut failed) = (0, 0);
+    for (user_id, phone_number) in stored {
+        let Ok(normalized) = phones.normalize(&phone_number) else {
+            println!("User {user_id}: {phone_number} is not a valid phone number");
+            failed += 1;
+            continue;
+        };
+        if normalized == phone_number {
+            continue;
+        }
+        match diesel::update(users::table.find(user_id))
+            .set(users::phone_number.eq(&normalized))
+            .execute(&mut conn)
+        {
+            Ok(_) => updated += 1,
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                println!("User {user_id}: {phone_number} is {normalized}, which another user has");
+                failed += 1;
+            }
+            Err(err) => return Err(io::Error::other(err)),
+        }
+    }
+    println!("Normalized {updated} phone numbers");
+
+    if failed > 0 {
+        println!("{failed} phone numbers need to be fixed by hand, then run this again");
+        return Ok(());
+    }
+    println!("All phone numbers are in E.164, the phone_number_e164 migration can run now");
+    Ok(())
+}
+
+fn connect() -> io::Result<PgConnection> {
+    dotenvy::dotenv().ok();
+    let database_url = env::var("DATABASE_URL")
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "DATABASE_URL must be set"))?;
+    PgConnection::establish(&database_url).map_err(io::Error::other)
+}
@@ -0,0 +1,48 @@
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use data_encoding::BASE32HEX_NOPAD;
+use password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Marks our keys, so leaked ones are easy to recognize by secret scanners.
+const KEY_MARKER: &str = "hvp";
+
+/// A newly generated key, `key` is shown to the user once and only its hash is stored.
+pub struct GeneratedKey {
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+}
+
+/// Generate a key of the form `hvp_{prefix}_{secret}`.
+pub fn generate() -> GeneratedKey {
+    let mut prefix = [0_u8; 5];
+    OsRng.fill_bytes(&mut prefix);
+    let prefix = BASE32HEX_NOPAD.encode(&prefix).to_lowercase();
+
+    let mut secret = [0_u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let key = format!("{KEY_MARKER}_{prefix}_{}", URL_SAFE_NO_PAD.encode(secret));
+
+    GeneratedKey {
+        hash: hash(&key),
+        key,
+        prefix,
+    }
+}
+
+/// The prefix of a key, used to look it up.
+pub fn prefix(key: &str) -> Option<&str> {
+    let (prefix, _) = key
+        .strip_prefix(KEY_MARKER)?
+        .strip_prefix('_')?
+        .split_once('_')?;
+    Some(prefix)
+}
+
+/// Keys are random, so an unsalted hash is enough to keep them from being read.
+pub fn hash(key: &str) -> String {
+    STANDARD.encode(Sha256::digest(key.as_bytes()))
+}
@@ -0,0 +1,131 @@
+use api::models::user::VerificationChannel;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use password_hash::rand_core::{OsRng, RngCore};
+use redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::verification::channel_name;
+
+/// How long the code sent to a new address is valid.
+const CODE_TTL_SECS: u64 = 600;
+/// Wrong codes accepted before the change is discarded.
+const MAX_ATTEMPTS: i64 = 5;
+
+pub enum ChangeConfirmation {
+    /// The code was correct, the new address to store.
+    Confirmed(String),
+    Invalid,
+    Expired,
+    TooManyAttempts,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingChange {
+    address: String,
+    code_hash: String,
+}
+
+/// Email and phone changes waiting for the code sent to the new address, stored in Redis.
+///
+/// A user has at most one pending change per channel, requesting another replaces it.
+#[derive(Clone)]
+pub struct ContactChanges {
+    redis: MultiplexedConnection,
+}
+
+impl ContactChanges {
+    pub fn new(redis: MultiplexedConnection) -> Self {
+        Self { redis }
+    }
+
+    /// Remember the change of the user's `channel` address to `address`, returns the code to send
+    /// to it.
+    pub async fn begin(
+        &self,
+        user_id: i32,
+        channel: VerificationChannel,
+        address: &str,
+    ) -> RedisResult<String> {
+        let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+        let pending = PendingChange {
+            address: address.to_string(),
+            code_hash: code_hash(&code),
+        };
+        let value = serde_json::to_string(&pending).expect("pending change serializes");
+        redis::pipe()
+            .del(attempts_key(user_id, channel))
+            .set_ex(change_key(user_id, channel), value, CODE_TTL_SECS)
+            .query_async::<()>(&mut self.redis.clone())
+            .await?;
+        Ok(code)
+    }
+
+    /// Check a submitted code, a correct code can only be used once.
+    pub async fn confirm(
+        &self,
+        user_id: i32,
+        channel: VerificationChannel,
+        code: &str,
+    ) -> RedisResult<ChangeConfirmation> {
+        let mut redis = self.redis.clone();
+        let value: Option<String> = redis.get(change_key(user_id, channel)).await?;
+        let Some(pending) =
+            value.and_then(|value| serde_json::from_str::<PendingChange>(&value).ok())
+        else {
+            return Ok(ChangeConfirmation::Expired);
+        };
+
+        let attempts: i64 = redis.incr(attempts_key(user_id, channel), 1).await?;
+        if attempts == 1 {
+            redis
+                .expire::<_, ()>(attempts_key(user_id, channel), CODE_TTL_SECS as i64)
+                .await?;
+        }
+        if attempts > MAX_ATTEMPTS {
+            self.clear(user_id, channel).await?;
+            return Ok(ChangeConfirmation::TooManyAttempts);
+        }
+        if pending.code_hash != code_hash(code.trim()) {
+            return Ok(ChangeConfirmation::Invalid);
+        }
+
+        // Only the request deleting the change applies it, concurrent ones lose
+        let deleted: i64 = redis.del(change_key(user_id, channel)).await?;
+        if deleted == 0 {
+            return Ok(ChangeConfirmation::Expired);
+        }
+        self.clear(user_id, channel).await?;
+        Ok(ChangeConfirmation::Confirmed(pending.address))
+    }
+
+    /// Text of the message carrying `code` to the new address.
+    pub fn message(&self, code: &str) -> String {
+        format!(
+            "Your confirmation code is {code}, valid for {} minutes.",
+            CODE_TTL_SECS / 60
+        )
+    }
+
+    async fn clear(&self, user_id: i32, channel: VerificationChannel) -> RedisResult<()> {
+        self.redis
+            .clone()
+            .del(&[change_key(user_id, channel), attempts_key(user_id, channel)])
+            .await
+    }
+}
+
+fn code_hash(code: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code.as_bytes()))
+}
+
+fn change_key(user_id: i32, channel: VerificationChannel) -> String {
+    format!("contact_change:{user_id}:{}", channel_name(channel))
+}
+
+fn attempts_key(user_id: i32, channel: VerificationChannel) -> String {
+    format!(
+        "contact_change_attempts:{user_id}:{}",
+        channel_name(channel)
+    )
+}
@@ -0,0 +1,96 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use password_hash::rand_core::{OsRng, RngCore};
+use redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+use sha2::{Digest, Sha256};
+
+use crate::common::passwordless::Redemption;
+
+/// How long the code confirming a deletion is valid.
+const CODE_TTL_SECS: u64 = 600;
+/// Wrong codes accepted before the code is discarded.
+const MAX_ATTEMPTS: i64 = 5;
+
+/// Codes emailed to confirm the deletion of an account, stored in Redis as hashes.
+///
+/// They stand in for the password of accounts that never had a usable one, e.g. created through
+/// an OIDC provider, or whose owner only signs in with login codes. Anyone able to read the
+/// mailbox could set a new password through a reset anyway, so they are not weaker than one.
+#[derive(Clone)]
+pub struct DeletionCodes {
+    redis: MultiplexedConnection,
+}
+
+impl DeletionCodes {
+    pub fn new(redis: MultiplexedConnection) -> Self {
+        Self { redis }
+    }
+
+    /// Issue a code for the user, replacing the one issued before.
+    pub async fn issue(&self, user_id: i32) -> RedisResult<String> {
+        let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+        redis::pipe()
+            .del(attempts_key(user_id))
+            .set_ex(code_key(user_id), code_hash(&code), CODE_TTL_SECS)
+            .query_async::<()>(&mut self.redis.clone())
+            .await?;
+        Ok(code)
+    }
+
+    /// Check a submitted code, a correct code can only be used once.
+    pub async fn redeem(&self, user_id: i32, code: &str) -> RedisResult<Redemption> {
+        let mut redis = self.redis.clone();
+        let Some(expected): Option<String> = redis.get(code_key(user_id)).await? else {
+            return Ok(Redemption::Expired);
+        };
+
+        let attempts: i64 = redis.incr(attempts_key(user_id), 1).await?;
+        if attempts == 1 {
+            redis
+                .expire::<_, ()>(attempts_key(user_id), CODE_TTL_SECS as i64)
+                .await?;
+        }
+        if attempts > MAX_ATTEMPTS {
+            self.clear(user_id).await?;
+            return Ok(Redemption::TooManyAttempts);
+        }
+        if expected != code_hash(code.trim()) {
+            return Ok(Redemption::Invalid);
+        }
+
+        // Only the request deleting the code goes on, concurrent ones lose
+        let deleted: i64 = redis.del(code_key(user_id)).await?;
+        if deleted == 0 {
+            return Ok(Redemption::Expired);
+        }
+        self.clear(user_id).await?;
+        Ok(Redemption::Valid)
+    }
+
+    /// Text of the message carrying `code`.
+    pub fn message(&self, code: &str) -> String {
+        format!(
+            "Your code to delete your account is {code}, valid for {} minutes. If you did not \
+             ask to delete your account, sign out of your other sessions and contact support.",
+            CODE_TTL_SECS / 60
+        )
+    }
+
+    async fn clear(&self, user_id: i32) -> RedisResult<()> {
+        self.redis
+            .clone()
+            .del(&[code_key(user_id), attempts_key(user_id)])
+            .await
+    }
+}
+
+fn code_hash(code: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code.as_bytes()))
+}
+
+fn code_key(user_id: i32) -> String {
+    format!("deletion_code:{user_id}")
+}
+
+fn attempts_key(user_id: i32) -> String {
+    format!("deletion_code_attempts:{user_id}")
+}
@@ -0,0 +1,131 @@
+use std::env;
+
+use api::schema::{
+    api_keys, audit_events, mfa_recovery_codes, user_identities, user_sessions, user_totp, users,
+};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::{dsl::now, prelude::*};
+
+/// Name erased accounts are left with.
+const ERASED_NAME: &str = "Deleted user";
+
+/// Deletion of accounts on request of their users.
+///
+/// Requesting the deletion logs the user out everywhere and revokes their API keys, signing in
+/// again within the grace period cancels it. After it the personal data in `users` is
+/// overwritten and the rows only describing the user are deleted. The row itself stays, so
+/// bookings and the transactions of them are kept intact for accounting, and so do the user's
+/// audit events, without the IP addresses and user agents they were recorded with.
+pub struct AccountErasure {
+    grace: TimeDelta,
+}
+
+impl AccountErasure {
+    /// Reads the grace period from `ACCOUNT_DELETION_GRACE_DAYS`, 30 days by default.
+    pub fn from_env() -> Self {
+        let days = env::var("ACCOUNT_DELETION_GRACE_DAYS")
+            .ok()
+            .map(|value| {
+                value
+                    .parse()
+                    .expect("ACCOUNT_DELETION_GRACE_DAYS must be a number of days")
+            })
+            .unwrap_or(30);
+        Self {
+            grace: TimeDelta::days(days),
+        }
+    }
+
+    /// Record the user's deletion request and revoke their API keys, returns when the account
+    /// will be erased.
+    pub fn request(&self, conn: &mut PgConnection, user_id: i32) -> QueryResult<NaiveDateTime> {
+        conn.transaction(|conn| {
+            let requested_at = diesel::update(users::table.find(user_id))
+                .set((
+                    users::deletion_requested_at.eq(now.nullable()),
+                    users::updated_at.eq(now),
+                ))
+                .returning(users::deletion_requested_at)
+                .get_result::<Option<NaiveDateTime>>(conn)?
+                .unwrap_or_else(|| Utc::now().naive_utc());
+            // Keys do not come back when the request is cancelled, new ones can be created
+            diesel::update(
+                api_keys::table
+                    .filter(api_keys::user_id.eq(user_id))
+                    .filter(api_keys::revoked_at.is_null()),
+            )
+            .set(api_keys::revoked_at.eq(now.nullable()))
+            .execute(conn)?;
+            Ok(requested_at + self.grace)
+        })
+    }
+
+    /// Erase the accounts whose grace period is over, returns how many were erased.
+    pub fn erase_due(&self, conn: &mut PgConnection) -> QueryResult<usize> {
+        let due = users::table
+            .filter(users::deletion_requested_at.le((Utc::now() - self.grace).naive_utc()))
+            .filter(users::erased_at.is_null())
+            .select(users::id)
+            .load::<i32>(conn)?;
+        for user_id in &due {
+            erase(conn, *user_id)?;
+        }
+        Ok(due.len())
+    }
+}
+
+/// Withdraw a deletion request, a no-op for users without one.
+pub fn cancel(conn: &mut PgConnection, user_id: i32) -> QueryResult<()> {
+    diesel::update(
+        users::table
+            .find(user_id)
+            .filter(users::deletion_requested_at.is_not_null())
+            .filter(users::erased_at.is_null()),
+    )
+    .set(users::deletion_requested_at.eq(None::<NaiveDateTime>))
+    .execute(conn)
+    .map(drop)
+}
+
+fn erase(conn: &mut PgConnection, user_id: i32) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        diesel::delete(user_sessions::table.filter(user_sessions::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(user_identities::table.filter(user_identities::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(user_id)))
+            .execute(conn)?;
+        diesel::delete(user_totp::table.find(user_id)).execute(conn)?;
+        diesel::delete(api_keys::table.filter(api_keys::user_id.eq(user_id))).execute(conn)?;
+        // The only update the audit log allows, the events themselves stay
+        diesel::update(
+            audit_events::table.filter(
+                audit_events::actor_id
+                    .eq(user_id)
+                    .or(audit_events::subject_id.eq(user_id)),
+            ),
+        )
+        .set((
+            audit_events::ip_address.eq(None::<String>),
+            audit_events::user_agent.eq(None::<String>),
+        ))
+        .execute(conn)?;
+
+        // An empty password is no valid hash, so nobody can log in as the erased user
+        diesel::update(users::table.find(user_id))
+            .set((
+                users::name.eq(ERASED_NAME),
+                users::email.eq(format!("erased-{user_id}@erased.invalid")),
+                users::password.eq(""),
+                users::phone_number.eq(None::<String>),
+                users::professional_info.eq(None::<serde_json::Value>),
+                users::email_verified_at.eq(None::<NaiveDateTime>),
+                users::phone_verified_at.eq(None::<NaiveDateTime>),
+                users::token_version.eq(users::token_version + 1),
+                users::erased_at.eq(now.nullable()),
+                users::updated_at.eq(now),
+            ))
+            .execute(conn)
+            .map(drop)
+    })
+}
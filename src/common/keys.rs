@@ -0,0 +1,214 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ed25519_compact::{KeyPair, PublicKey, SecretKey};
+use jwt_compact::{
+    Algorithm,
+    alg::Ed25519,
+    jwk::{JsonWebKey, JwkError},
+};
+use serde_json::{Value, json};
+use sha2::Sha256;
+
+#[derive(Debug)]
+pub enum KeyError {
+    NotConfigured,
+    Io(PathBuf, io::Error),
+    Pem(ed25519_compact::Error),
+    Jwk(String),
+    PublicOnly,
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::NotConfigured => write!(
+                f,
+                "no signing key configured, set JWT_SIGNING_KEY or JWT_SIGNING_KEY_FILE \
+                 (generate one with `keys generate`)"
+            ),
+            KeyError::Io(path, err) => write!(f, "failed to read {}: {err}", path.display()),
+            KeyError::Pem(err) => write!(f, "invalid PEM key: {err}"),
+            KeyError::Jwk(err) => write!(f, "invalid JWK: {err}"),
+            KeyError::PublicOnly => write!(f, "a private key is required for signing"),
+        }
+    }
+}
+
+impl From<JwkError> for KeyError {
+    fn from(err: JwkError) -> Self {
+        KeyError::Jwk(err.to_string())
+    }
+}
+
+/// Key material read from a PEM or JWK document.
+enum KeyMaterial {
+    Signing(KeyPair),
+    Verifying(PublicKey),
+}
+
+impl KeyMaterial {
+    fn parse(text: &str) -> Result<Self, KeyError> {
+        let text = text.trim();
+        if text.starts_with('{') {
+            let jwk: JsonWebKey<'_> =
+                serde_json::from_str(text).map_err(|err| KeyError::Jwk(err.to_string()))?;
+            return if jwk.is_signing_key() {
+                let sk = SecretKey::try_from(&jwk)?;
+                Ok(KeyMaterial::Signing(KeyPair {
+                    pk: sk.public_key(),
+                    sk,
+                }))
+            } else {
+                Ok(KeyMaterial::Verifying(PublicKey::try_from(&jwk)?))
+            };
+        }
+
+        if text.contains("PUBLIC KEY") {
+            PublicKey::from_pem(text)
+                .map(KeyMaterial::Verifying)
+                .map_err(KeyError::Pem)
+        } else {
+            KeyPair::from_pem(text)
+                .map(KeyMaterial::Signing)
+                .map_err(KeyError::Pem)
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self, KeyError> {
+        let text = fs::read_to_string(path).map_err(|err| KeyError::Io(path.into(), err))?;
+        Self::parse(&text)
+    }
+
+    fn public_key(&self) -> PublicKey {
+        match self {
+            KeyMaterial::Signing(pair) => pair.pk,
+            KeyMaterial::Verifying(pk) => *pk,
+        }
+    }
+}
+
+/// Public key together with its `kid`.
+#[derive(Debug, Clone)]
+pub struct VerifyingEntry {
+    pub kid: String,
+    pub key: PublicKey,
+}
+
+/// Signing key used for newly issued tokens plus keys retired from signing that are still
+/// accepted for verification during a rotation window.
+#[derive(Clone)]
+pub struct Keyring {
+    kid: String,
+    signing_key: SecretKey,
+    verifying: Vec<VerifyingEntry>,
+}
+
+impl Keyring {
+    /// Load the keyring from the environment.
+    ///
+    /// - `JWT_SIGNING_KEY`: inline PEM or JWK private key
+    /// - `JWT_SIGNING_KEY_FILE`: path to a PEM or JWK private key, used when the above is unset
+    /// - `JWT_RETIRED_KEY_FILES`: comma separated paths to retired keys, public or private
+    pub fn from_env() -> Result<Self, KeyError> {
+        let current = match (
+            env::var("JWT_SIGNING_KEY"),
+            env::var_os("JWT_SIGNING_KEY_FILE"),
+        ) {
+            (Ok(inline), _) => KeyMaterial::parse(&inline)?,
+            (Err(_), Some(path)) => KeyMaterial::read(Path::new(&path))?,
+            (Err(_), None) => return Err(KeyError::NotConfigured),
+        };
+        let KeyMaterial::Signing(pair) = current else {
+            return Err(KeyError::PublicOnly);
+        };
+
+        let retired = env::var("JWT_RETIRED_KEY_FILES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(|path| KeyMaterial::read(Path::new(path)).map(|key| key.public_key()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(pair, retired))
+    }
+
+    pub fn new(pair: KeyPair, retired: Vec<PublicKey>) -> Self {
+        let kid = key_id(&pair.pk);
+        let mut verifying = vec![VerifyingEntry {
+            kid: kid.clone(),
+            key: pair.pk,
+        }];
+        for key in retired {
+            let kid = key_id(&key);
+            if verifying.iter().all(|entry| entry.kid != kid) {
+                verifying.push(VerifyingEntry { kid, key });
+            }
+        }
+
+        Self {
+            kid,
+            signing_key: pair.sk,
+            verifying,
+        }
+    }
+
+    /// `kid` of the key currently used for signing.
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn signing_key(&self) -> &SecretKey {
+        &self.signing_key
+    }
+
+    /// Key accepted for verifying tokens carrying the given `kid`.
+    pub fn verifying_key(&self, kid: &str) -> Option<&PublicKey> {
+        self.verifying
+            .iter()
+            .find(|entry| entry.kid == kid)
+            .map(|entry| &entry.key)
+    }
+
+    /// Public keys as a JSON Web Key Set.
+    pub fn jwks(&self) -> Value {
+        let keys: Vec<Value> = self
+            .verifying
+            .iter()
+            .map(|entry| {
+                let mut jwk = serde_json::to_value(JsonWebKey::from(&entry.key))
+                    .expect("JWK serialization failed");
+                jwk["kid"] = json!(entry.kid);
+                jwk["alg"] = json!(Ed25519.name());
+                jwk["use"] = json!("sig");
+                jwk
+            })
+            .collect();
+        json!({ "keys": keys })
+    }
+}
+
+/// RFC 7638 thumbprint of the public key, used as its `kid`.
+pub fn key_id(key: &PublicKey) -> String {
+    URL_SAFE_NO_PAD.encode(JsonWebKey::from(key).thumbprint::<Sha256>())
+}
+
+/// Generate a new signing key and write it as `<kid>.pem` into `dir`.
+pub fn generate_key_file(dir: &Path) -> io::Result<(String, PathBuf)> {
+    let pair = KeyPair::generate();
+    let kid = key_id(&pair.pk);
+    let path = dir.join(format!("{kid}.pem"));
+
+    fs::create_dir_all(dir)?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    io::Write::write_all(&mut options.open(&path)?, pair.sk.to_pem().as_bytes())?;
+
+    Ok((kid, path))
+}
@@ -0,0 +1,152 @@
+use std::{env, net::IpAddr};
+
+use redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+
+/// How long consecutive lockouts of the same key are remembered to grow the next one.
+const LOCKOUT_STREAK_SECS: u64 = 24 * 60 * 60;
+
+/// A lockout started by a failed login.
+pub struct Lockout {
+    pub retry_after_secs: u64,
+    /// Whether the account itself was locked, not only the address the attempts came from.
+    pub account: bool,
+}
+
+/// Failed login counters per email and per client IP stored in Redis.
+///
+/// Once either counter reaches the threshold, logins for that email or from that IP are locked
+/// out. Every further lockout within a day doubles in length, up to a maximum.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    redis: MultiplexedConnection,
+    max_failures: u64,
+    failure_window_secs: u64,
+    lockout_secs: u64,
+    max_lockout_secs: u64,
+}
+
+impl LoginThrottle {
+    /// Reads `LOGIN_MAX_FAILED_ATTEMPTS` (5), `LOGIN_FAILURE_WINDOW` (900), `LOGIN_LOCKOUT` (60)
+    /// and `LOGIN_MAX_LOCKOUT` (3600), all durations in seconds.
+    pub fn from_env(redis: MultiplexedConnection) -> Self {
+        Self {
+            redis,
+            max_failures: number_from_env("LOGIN_MAX_FAILED_ATTEMPTS", 5),
+            failure_window_secs: number_from_env("LOGIN_FAILURE_WINDOW", 900),
+            lockout_secs: number_from_env("LOGIN_LOCKOUT", 60),
+            max_lockout_secs: number_from_env("LOGIN_MAX_LOCKOUT", 3600),
+        }
+    }
+
+    /// Seconds until logins for `email` from `ip` are allowed again, if locked out.
+    pub async fn locked_for(&self, email: &str, ip: Option<IpAddr>) -> RedisResult<Option<u64>> {
+        let mut pipe = redis::pipe();
+        pipe.ttl(lockout_key(&email_scope(email)));
+        if let Some(ip) = ip {
+            pipe.ttl(lockout_key(&ip_scope(ip)));
+        }
+        let ttls: Vec<i64> = pipe.query_async(&mut self.redis.clone()).await?;
+        Ok(ttls
+            .into_iter()
+            .filter(|&ttl| ttl > 0)
+            .max()
+            .map(|ttl| ttl as u64))
+    }
+
+    /// Count a failed login, returns the lockout it started if any.
+    pub async fn record_failure(
+        &self,
+        email: &str,
+        ip: Option<IpAddr>,
+    ) -> RedisResult<Option<Lockout>> {
+        let mut lockout = self
+            .count_failure(&email_scope(email))
+            .await?
+            .map(|retry_after_secs| Lockout {
+                retry_after_secs,
+                account: true,
+            });
+        if let Some(ip) = ip
+            && let Some(secs) = self.count_failure(&ip_scope(ip)).await?
+        {
+            let account = lockout.is_some();
+            let retry_after_secs = lockout.map_or(secs, |l| l.retry_after_secs.max(secs));
+            lockout = Some(Lockout {
+                retry_after_secs,
+                account,
+            });
+        }
+        Ok(lockout)
+    }
+
+    /// Forget the failed logins for `email`, failures of the IP are kept so one valid account
+    /// cannot be used to reset them.
+    pub async fn record_success(&self, email: &str) -> RedisResult<()> {
+        let scope = email_scope(email);
+        self.redis
+            .clone()
+            .del(&[failures_key(&scope), streak_key(&scope)])
+            .await
+    }
+
+    async fn count_failure(&self, scope: &str) -> RedisResult<Option<u64>> {
+        let mut redis = self.redis.clone();
+        let (failures,): (u64,) = redis::pipe()
+            .incr(failures_key(scope), 1)
+            .expire(failures_key(scope), self.failure_window_secs as i64)
+            .ignore()
+            .query_async(&mut redis)
+            .await?;
+        if failures < self.max_failures {
+            return Ok(None);
+        }
+
+        let (streak,): (u32,) = redis::pipe()
+            .incr(streak_key(scope), 1)
+            .expire(streak_key(scope), LOCKOUT_STREAK_SECS as i64)
+            .ignore()
+            .del(failures_key(scope))
+            .ignore()
+            .query_async(&mut redis)
+            .await?;
+        let secs = self
+            .lockout_secs
+            .saturating_mul(2_u64.saturating_pow(streak.saturating_sub(1)))
+            .min(self.max_lockout_secs);
+        redis
+            .set_ex::<_, _, ()>(lockout_key(scope), 1, secs)
+            .await?;
+        Ok(Some(secs))
+    }
+}
+
+fn number_from_env(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{name} must be a positive number"))
+        })
+        .unwrap_or(default)
+}
+
+fn email_scope(email: &str) -> String {
+    format!("email:{}", email.trim().to_lowercase())
+}
+
+fn ip_scope(ip: IpAddr) -> String {
+    format!("ip:{ip}")
+}
+
+fn failures_key(scope: &str) -> String {
+    format!("login_failures:{scope}")
+}
+
+fn streak_key(scope: &str) -> String {
+    format!("login_lockouts:{scope}")
+}
+
+fn lockout_key(scope: &str) -> String {
+    format!("login_lockout:{scope}")
+}
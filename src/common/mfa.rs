@@ -0,0 +1,211 @@
+use std::env;
+
+use aes_gcm::{
+    AeadCore, Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, generic_array::typenum::Unsigned},
+};
+use api::{
+    models::mfa::UserTotp,
+    schema::{mfa_recovery_codes, user_totp},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::Utc;
+use data_encoding::{BASE32_NOPAD, BASE32HEX_NOPAD};
+use diesel::{dsl::now, prelude::*};
+use hmac::{Hmac, Mac};
+use password_hash::rand_core::{OsRng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Length of a TOTP time step in seconds, see RFC 6238.
+const STEP_SECS: i64 = 30;
+/// Digits of a TOTP code.
+const DIGITS: u32 = 6;
+/// Time steps before and after the current one that are still accepted, for clock drift.
+const ALLOWED_DRIFT: i64 = 1;
+/// Bytes of a TOTP secret, the HMAC-SHA1 output size recommended by RFC 4226.
+const SECRET_LEN: usize = 20;
+/// Recovery codes issued on enrollment.
+const RECOVERY_CODES: usize = 10;
+
+/// TOTP secrets encryption and verification of second factors.
+pub struct Mfa {
+    cipher: Aes256Gcm,
+    issuer: String,
+}
+
+impl Mfa {
+    /// Reads `MFA_ENCRYPTION_KEY`, 32 base64 encoded bytes, and `TOTP_ISSUER`, the name shown in
+    /// authenticator apps.
+    pub fn from_env() -> Self {
+        let key = env::var("MFA_ENCRYPTION_KEY").expect("MFA_ENCRYPTION_KEY must be set");
+        let key = STANDARD
+            .decode(key.trim())
+            .expect("MFA_ENCRYPTION_KEY must be base64");
+        let key: [u8; 32] = key
+            .try_into()
+            .expect("MFA_ENCRYPTION_KEY must be 32 bytes long");
+        Self::new(
+            &key,
+            env::var("TOTP_ISSUER").unwrap_or_else(|_| "HavenlyPro".to_string()),
+        )
+    }
+
+    pub fn new(key: &[u8; 32], issuer: String) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(key.into()),
+            issuer,
+        }
+    }
+
+    /// Generate a new secret, returns it encrypted for storage and base32 encoded for the user.
+    pub fn generate_secret(&self) -> (Vec<u8>, String) {
+        let mut secret = [0_u8; SECRET_LEN];
+        OsRng.fill_bytes(&mut secret);
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(
+            self.cipher
+                .encrypt(&nonce, secret.as_slice())
+                .expect("secret encryption failed"),
+        );
+        (encrypted, BASE32_NOPAD.encode(&secret))
+    }
+
+    /// `otpauth://` URI to enroll `secret` in an authenticator app, usually shown as QR code.
+    pub fn provisioning_uri(&self, account: &str, secret: &str) -> String {
+        let issuer = encode_uri_component(&self.issuer);
+        format!(
+            "otpauth://totp/{issuer}:{}?secret={secret}&issuer={issuer}&algorithm=SHA1\
+             &digits={DIGITS}&period={STEP_SECS}",
+            encode_uri_component(account),
+        )
+    }
+
+    /// Check a TOTP code against a stored secret, returns the matched time step.
+    ///
+    /// Steps up to `last_used_step` are skipped, so a code cannot be replayed.
+    pub fn verify_code(&self, totp: &UserTotp, code: &str) -> Option<i64> {
+        let code: u32 = code
+            .trim()
+            .parse()
+            .ok()
+            .filter(|_| code.trim().len() == DIGITS as usize)?;
+        let Some(secret) = self.decrypt(&totp.secret) else {
+            log::error!("Failed to decrypt the TOTP secret of user {}", totp.user_id);
+            return None;
+        };
+
+        let current = Utc::now().timestamp() / STEP_SECS;
+        (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT)
+            .filter(|&step| totp.last_used_step.is_none_or(|last| step > last))
+            .find(|&step| code_at(&secret, step) == code)
+    }
+
+    /// Whether the user has confirmed a TOTP enrollment.
+    pub fn is_enabled(&self, conn: &mut PgConnection, user_id: i32) -> QueryResult<bool> {
+        diesel::select(diesel::dsl::exists(
+            user_totp::table
+                .find(user_id)
+                .filter(user_totp::enabled_at.is_not_null()),
+        ))
+        .get_result(conn)
+    }
+
+    /// Check a second factor, a TOTP code or an unused recovery code, and mark it as used.
+    pub fn verify(&self, conn: &mut PgConnection, user_id: i32, code: &str) -> QueryResult<bool> {
+        conn.transaction(|conn| {
+            let Some(totp) = user_totp::table
+                .find(user_id)
+                .filter(user_totp::enabled_at.is_not_null())
+                .for_update()
+                .first::<UserTotp>(conn)
+                .optional()?
+            else {
+                return Ok(false);
+            };
+
+            if let Some(step) = self.verify_code(&totp, code) {
+                diesel::update(user_totp::table.find(user_id))
+                    .set(user_totp::last_used_step.eq(step))
+                    .execute(conn)?;
+                return Ok(true);
+            }
+
+            let used = diesel::update(
+                mfa_recovery_codes::table
+                    .filter(mfa_recovery_codes::user_id.eq(user_id))
+                    .filter(mfa_recovery_codes::code_hash.eq(recovery_code_hash(code)))
+                    .filter(mfa_recovery_codes::used_at.is_null()),
+            )
+            .set(mfa_recovery_codes::used_at.eq(now.nullable()))
+            .execute(conn)?;
+            Ok(used > 0)
+        })
+    }
+
+    fn decrypt(&self, encrypted: &[u8]) -> Option<Vec<u8>> {
+        let nonce_len = <Aes256Gcm as AeadCore>::NonceSize::USIZE;
+        if encrypted.len() < nonce_len {
+            return None;
+        }
+        let (nonce, ciphertext) = encrypted.split_at(nonce_len);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+}
+
+/// Generate a set of one-time recovery codes, shown to the user once and stored hashed.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0_u8; 5];
+            OsRng.fill_bytes(&mut bytes);
+            let code = BASE32HEX_NOPAD.encode(&bytes).to_lowercase();
+            format!("{}-{}", &code[..4], &code[4..])
+        })
+        .collect()
+}
+
+/// Hash of a recovery code, ignoring case, whitespace and dashes.
+///
+/// Recovery codes are random, so an unsalted hash is enough to keep them from being read.
+pub fn recovery_code_hash(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    STANDARD.encode(Sha256::digest(normalized.as_bytes()))
+}
+
+/// HOTP value of `secret` for the counter `step`, see RFC 4226.
+fn code_at(secret: &[u8], step: i64) -> u32 {
+    let mut mac =
+        <Hmac<Sha1> as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+    truncated % 10_u32.pow(DIGITS)
+}
+
+fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
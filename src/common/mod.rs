@@ -0,0 +1,17 @@
+pub mod api_keys;
+pub mod contact_changes;
+pub mod deletion_codes;
+pub mod erasure;
+pub mod keys;
+pub mod login_throttle;
+pub mod mfa;
+pub mod notifications;
+pub mod oidc;
+pub mod password_reset;
+pub mod passwordless;
+pub mod passwords;
+pub mod refresh_tokens;
+pub mod revocation;
+pub mod sessions;
+pub mod tokens;
+pub mod verification;
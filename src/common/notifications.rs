@@ -0,0 +1,42 @@
+use api::models::notification::{NOTIFICATIONS_QUEUE, NotificationMessage};
+use lapin::{
+    BasicProperties, Channel,
+    options::{BasicPublishOptions, QueueDeclareOptions},
+    types::FieldTable,
+};
+
+/// Publishes messages for the notification worker.
+#[derive(Clone)]
+pub struct Notifier {
+    channel: Channel,
+}
+
+impl Notifier {
+    /// Declares the queue the same way the worker does, so messages published before the worker
+    /// first started are kept.
+    pub async fn new(channel: Channel) -> lapin::Result<Self> {
+        channel
+            .queue_declare(
+                NOTIFICATIONS_QUEUE,
+                QueueDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        Ok(Self { channel })
+    }
+
+    pub async fn send(&self, message: &NotificationMessage) -> lapin::Result<()> {
+        let payload = serde_json::to_vec(message).expect("notification serialization failed");
+        self.channel
+            .basic_publish(
+                "",
+                NOTIFICATIONS_QUEUE,
+                BasicPublishOptions::default(),
+                &payload,
+                BasicProperties::default().with_content_type("application/json".into()),
+            )
+            .await?
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::{collections::HashMap, env, fmt, future::Future, pin::Pin};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use password_hash::rand_core::{OsRng, RngCore};
+use redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::common::tokens::random_id;
+
+/// How long a user has to sign in at the provider, in seconds.
+pub const STATE_LIFETIME_SECS: u64 = 600;
+
+/// The account a user signed in with at a provider.
+#[derive(Debug, Clone)]
+pub struct OidcIdentity {
+    /// `sub` claim, stable for the account unlike the email.
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    Http(reqwest::Error),
+    /// The provider answered with an error, e.g. for an expired code.
+    Rejected(String),
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Http(err) => write!(f, "request to provider failed: {err}"),
+            OidcError::Rejected(msg) => write!(f, "provider rejected the request: {msg}"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(err: reqwest::Error) -> Self {
+        OidcError::Http(err)
+    }
+}
+
+pub type IdentityFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<OidcIdentity, OidcError>> + Send + 'a>>;
+
+/// An OpenID Connect provider users can sign in with through the authorization code flow.
+///
+/// Implemented by [`ConfiguredProvider`] for real providers, tests can register a mock identity
+/// provider with [`Oidc::with_provider`].
+pub trait OidcProvider: Send + Sync {
+    /// URL to send the user to, `code_challenge` is the S256 PKCE challenge.
+    fn authorization_url(&self, state: &str, code_challenge: &str, redirect_uri: &str) -> String;
+
+    /// Redeem the code the provider redirected back with for the identity of the user.
+    fn exchange_code<'a>(
+        &'a self,
+        code: &'a str,
+        code_verifier: &'a str,
+        redirect_uri: &'a str,
+    ) -> IdentityFuture<'a>;
+}
+
+/// Provider configured from its endpoints and client credentials.
+///
+/// The identity is read from the userinfo endpoint with the access token received directly from
+/// the token endpoint, so the ID token does not need to be verified.
+pub struct ConfiguredProvider {
+    client: reqwest::Client,
+    client_id: String,
+    client_secret: Option<String>,
+    authorization_url: Url,
+    token_url: Url,
+    userinfo_url: Url,
+    scopes: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct UserInfo {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    name: Option<String>,
+}
+
+impl ConfiguredProvider {
+    /// Reads `OIDC_{NAME}_CLIENT_ID`, `OIDC_{NAME}_CLIENT_SECRET` (optional for public clients),
+    /// `OIDC_{NAME}_AUTHORIZATION_URL`, `OIDC_{NAME}_TOKEN_URL`, `OIDC_{NAME}_USERINFO_URL` and
+    /// `OIDC_{NAME}_SCOPES`, "openid email profile" by default.
+    pub fn from_env(name: &str) -> Self {
+        let prefix = format!("OIDC_{}", name.to_uppercase());
+        let var = |suffix: &str| {
+            env::var(format!("{prefix}_{suffix}"))
+                .unwrap_or_else(|_| panic!("{prefix}_{suffix} must be set"))
+        };
+        let url = |suffix: &str| {
+            Url::parse(&var(suffix))
+                .unwrap_or_else(|err| panic!("{prefix}_{suffix} must be a URL: {err}"))
+        };
+        Self {
+            client: reqwest::Client::new(),
+            client_id: var("CLIENT_ID"),
+            client_secret: env::var(format!("{prefix}_CLIENT_SECRET")).ok(),
+            authorization_url: url("AUTHORIZATION_URL"),
+            token_url: url("TOKEN_URL"),
+            userinfo_url: url("USERINFO_URL"),
+            scopes: env::var(format!("{prefix}_SCOPES"))
+                .unwrap_or_else(|_| "openid email profile".to_string()),
+        }
+    }
+
+    async fn identity(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        redirect_uri: &str,
+    ) -> Result<OidcIdentity, OidcError> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &self.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let response = self
+            .client
+            .post(self.token_url.clone())
+            .form(&form)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(OidcError::Rejected(response.text().await?));
+        }
+        let token: TokenResponse = response.json().await?;
+
+        let response = self
+            .client
+            .get(self.userinfo_url.clone())
+            .bearer_auth(token.access_token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(OidcError::Rejected(response.text().await?));
+        }
+        let info: UserInfo = response.json().await?;
+        Ok(OidcIdentity {
+            subject: info.sub,
+            email: info.email,
+            email_verified: info.email_verified,
+            name: info.name,
+        })
+    }
+}
+
+impl OidcProvider for ConfiguredProvider {
+    fn authorization_url(&self, state: &str, code_challenge: &str, redirect_uri: &str) -> String {
+        let mut url = self.authorization_url.clone();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &self.scopes)
+            .append_pair("state", state)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256");
+        url.into()
+    }
+
+    fn exchange_code<'a>(
+        &'a self,
+        code: &'a str,
+        code_verifier: &'a str,
+        redirect_uri: &'a str,
+    ) -> IdentityFuture<'a> {
+        Box::pin(self.identity(code, code_verifier, redirect_uri))
+    }
+}
+
+/// Local identity provider for tests, signs everyone in as `identity` except for the code
+/// `"denied"`.
+#[cfg(test)]
+pub struct MockProvider {
+    pub identity: OidcIdentity,
+}
+
+#[cfg(test)]
+impl OidcProvider for MockProvider {
+    fn authorization_url(&self, state: &str, code_challenge: &str, redirect_uri: &str) -> String {
+        format!(
+            "https://idp.test/authorize?state={state}&code_challenge={code_challenge}\
+             &redirect_uri={redirect_uri}"
+        )
+    }
+
+    fn exchange_code<'a>(
+        &'a self,
+        code: &'a str,
+        _code_verifier: &'a str,
+        _redirect_uri: &'a str,
+    ) -> IdentityFuture<'a> {
+        Box::pin(async move {
+            match code {
+                "denied" => Err(OidcError::Rejected("invalid_grant".to_string())),
+                _ => Ok(self.identity.clone()),
+            }
+        })
+    }
+}
+
+/// Sign in that was started at `provider` and waits for its callback.
+#[derive(Serialize, Deserialize)]
+pub struct PendingSignIn {
+    pub provider: String,
+    pub code_verifier: String,
+}
+
+/// Sign in started by [`Oidc::begin`].
+pub struct StartedSignIn {
+    /// URL to send the user to.
+    pub url: String,
+    /// Value the provider echoes in the callback, to be bound to the browser that started it.
+    pub state: String,
+}
+
+/// The configured providers and the sign ins in progress, stored in Redis by their `state`.
+pub struct Oidc {
+    providers: HashMap<String, Box<dyn OidcProvider>>,
+    redirect_base_url: String,
+    redis: MultiplexedConnection,
+}
+
+impl Oidc {
+    /// Reads the comma separated provider names from `OIDC_PROVIDERS`, each configured by
+    /// [`ConfiguredProvider::from_env`], and `OIDC_REDIRECT_BASE_URL`, the public URL of this
+    /// API that `/oidc/{provider}/callback` is appended to.
+    pub fn from_env(redis: MultiplexedConnection) -> Self {
+        let names = env::var("OIDC_PROVIDERS").unwrap_or_default();
+        let mut oidc = Self {
+            providers: HashMap::new(),
+            redirect_base_url: env::var("OIDC_REDIRECT_BASE_URL")
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                .to_string(),
+            redis,
+        };
+        for name in names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            assert!(
+                !oidc.redirect_base_url.is_empty(),
+                "OIDC_REDIRECT_BASE_URL must be set when OIDC_PROVIDERS is"
+            );
+            oidc = oidc.with_provider(name, ConfiguredProvider::from_env(name));
+        }
+        oidc
+    }
+
+    /// Register `provider` under `name`, replacing one of the same name.
+    pub fn with_provider(mut self, name: &str, provider: impl OidcProvider + 'static) -> Self {
+        self.providers.insert(name.to_string(), Box::new(provider));
+        self
+    }
+
+    pub fn provider(&self, name: &str) -> Option<&dyn OidcProvider> {
+        self.providers.get(name).map(|provider| provider.as_ref())
+    }
+
+    /// Callback URL registered at the provider.
+    pub fn redirect_uri(&self, name: &str) -> String {
+        format!("{}/oidc/{name}/callback", self.redirect_base_url)
+    }
+
+    /// Start a sign in at the provider, `None` if no provider is configured under `name`.
+    pub async fn begin(&self, name: &str) -> RedisResult<Option<StartedSignIn>> {
+        let Some(provider) = self.provider(name) else {
+            return Ok(None);
+        };
+        let state = random_id();
+        let mut verifier = [0_u8; 32];
+        OsRng.fill_bytes(&mut verifier);
+        let pending = PendingSignIn {
+            provider: name.to_string(),
+            code_verifier: URL_SAFE_NO_PAD.encode(verifier),
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(pending.code_verifier.as_bytes()));
+
+        let value = serde_json::to_string(&pending).expect("pending sign in serializes");
+        self.redis
+            .clone()
+            .set_ex::<_, _, ()>(state_key(&state), value, STATE_LIFETIME_SECS)
+            .await?;
+        Ok(Some(StartedSignIn {
+            url: provider.authorization_url(&state, &challenge, &self.redirect_uri(name)),
+            state,
+        }))
+    }
+
+    /// Consume the sign in of `state`, `None` if it expired, was used or never existed.
+    pub async fn finish(&self, state: &str) -> RedisResult<Option<PendingSignIn>> {
+        let value: Option<String> = self.redis.clone().get_del(state_key(state)).await?;
+        Ok(value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+}
+
+fn state_key(state: &str) -> String {
+    format!("oidc_state:{state}")
+}
@@ -0,0 +1,94 @@
+use std::env;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+use sha2::{Digest, Sha256};
+
+use crate::common::tokens::random_id;
+
+/// Password reset tokens stored in Redis.
+///
+/// Only the SHA-256 hash of a token is stored, so a leaked Redis dump cannot be used to reset
+/// passwords. A user has at most one outstanding token and redeeming it deletes it.
+#[derive(Clone)]
+pub struct PasswordResets {
+    redis: MultiplexedConnection,
+    lifetime_secs: u64,
+    url: Option<String>,
+}
+
+impl PasswordResets {
+    /// Reads `PASSWORD_RESET_TOKEN_LIFETIME` in seconds, 15 minutes by default, and
+    /// `PASSWORD_RESET_URL`, the link the token is appended to in the email.
+    pub fn from_env(redis: MultiplexedConnection) -> Self {
+        let lifetime_secs = env::var("PASSWORD_RESET_TOKEN_LIFETIME")
+            .ok()
+            .map(|value| {
+                value
+                    .parse()
+                    .expect("PASSWORD_RESET_TOKEN_LIFETIME must be a number of seconds")
+            })
+            .unwrap_or(900);
+        Self {
+            redis,
+            lifetime_secs,
+            url: env::var("PASSWORD_RESET_URL").ok(),
+        }
+    }
+
+    /// Issue a token for the user, replacing the one issued before.
+    pub async fn issue(&self, user_id: i32) -> RedisResult<String> {
+        let mut redis = self.redis.clone();
+        let token = random_id();
+        let hash = token_hash(&token);
+
+        let previous: Option<String> = redis.get(user_key(user_id)).await?;
+        let mut pipe = redis::pipe();
+        if let Some(previous) = previous {
+            pipe.del(token_key(&previous));
+        }
+        pipe.set_ex(token_key(&hash), user_id, self.lifetime_secs)
+            .set_ex(user_key(user_id), &hash, self.lifetime_secs)
+            .query_async::<()>(&mut redis)
+            .await?;
+        Ok(token)
+    }
+
+    /// The user a token was issued for, without consuming it.
+    pub async fn peek(&self, token: &str) -> RedisResult<Option<i32>> {
+        self.redis.clone().get(token_key(&token_hash(token))).await
+    }
+
+    /// Consume a token, returns the user it was issued for unless it expired or was used.
+    pub async fn redeem(&self, token: &str) -> RedisResult<Option<i32>> {
+        let mut redis = self.redis.clone();
+        let user_id: Option<i32> = redis.get_del(token_key(&token_hash(token))).await?;
+        if let Some(user_id) = user_id {
+            redis.del::<_, ()>(user_key(user_id)).await?;
+        }
+        Ok(user_id)
+    }
+
+    /// Text of the email carrying `token`.
+    pub fn message(&self, token: &str) -> String {
+        let minutes = self.lifetime_secs / 60;
+        match &self.url {
+            Some(url) => format!(
+                "Reset your password within {minutes} minutes using this link: {url}?token={token}"
+            ),
+            None => format!("Your password reset token, valid for {minutes} minutes: {token}"),
+        }
+    }
+}
+
+fn token_hash(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+fn token_key(hash: &str) -> String {
+    format!("password_reset:{hash}")
+}
+
+fn user_key(user_id: i32) -> String {
+    format!("password_reset_user:{user_id}")
+}
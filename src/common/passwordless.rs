@@ -0,0 +1,170 @@
+use std::env;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use password_hash::rand_core::{OsRng, RngCore};
+use redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+use sha2::{Digest, Sha256};
+
+use crate::common::tokens::random_id;
+
+/// Wrong codes accepted before the code is discarded.
+const MAX_ATTEMPTS: i64 = 5;
+
+/// A login code and magic link sent to a user, both redeemable once.
+pub struct LoginChallenge {
+    pub code: String,
+    pub token: String,
+}
+
+pub enum Redemption {
+    Valid,
+    Invalid,
+    Expired,
+    TooManyAttempts,
+}
+
+/// Passwordless login codes and magic link tokens stored in Redis.
+///
+/// A user has at most one outstanding challenge, requesting a new one replaces it and redeeming
+/// either its code or its link deletes both. Only hashes are stored.
+#[derive(Clone)]
+pub struct PasswordlessLogins {
+    redis: MultiplexedConnection,
+    lifetime_secs: u64,
+    url: Option<String>,
+}
+
+impl PasswordlessLogins {
+    /// Reads `LOGIN_CODE_LIFETIME` in seconds, 10 minutes by default, and `LOGIN_LINK_URL`, the
+    /// page the magic link token is appended to.
+    pub fn from_env(redis: MultiplexedConnection) -> Self {
+        let lifetime_secs = env::var("LOGIN_CODE_LIFETIME")
+            .ok()
+            .map(|value| {
+                value
+                    .parse()
+                    .expect("LOGIN_CODE_LIFETIME must be a number of seconds")
+            })
+            .unwrap_or(600);
+        Self {
+            redis,
+            lifetime_secs,
+            url: env::var("LOGIN_LINK_URL").ok(),
+        }
+    }
+
+    /// Issue a code and magic link for the user, replacing the ones issued before.
+    pub async fn issue(&self, user_id: i32) -> RedisResult<LoginChallenge> {
+        let mut redis = self.redis.clone();
+        let challenge = LoginChallenge {
+            code: format!("{:06}", OsRng.next_u32() % 1_000_000),
+            token: random_id(),
+        };
+        let token_hash = secret_hash(&challenge.token);
+
+        let previous: Option<String> = redis.get(link_user_key(user_id)).await?;
+        let mut pipe = redis::pipe();
+        if let Some(previous) = previous {
+            pipe.del(link_key(&previous));
+        }
+        pipe.del(attempts_key(user_id))
+            .set_ex(
+                code_key(user_id),
+                secret_hash(&challenge.code),
+                self.lifetime_secs,
+            )
+            .set_ex(link_key(&token_hash), user_id, self.lifetime_secs)
+            .set_ex(link_user_key(user_id), &token_hash, self.lifetime_secs)
+            .query_async::<()>(&mut redis)
+            .await?;
+        Ok(challenge)
+    }
+
+    /// Check a code submitted for the user, a correct code can only be used once.
+    pub async fn redeem_code(&self, user_id: i32, code: &str) -> RedisResult<Redemption> {
+        let mut redis = self.redis.clone();
+        let Some(expected): Option<String> = redis.get(code_key(user_id)).await? else {
+            return Ok(Redemption::Expired);
+        };
+
+        let attempts: i64 = redis.incr(attempts_key(user_id), 1).await?;
+        if attempts == 1 {
+            redis
+                .expire::<_, ()>(attempts_key(user_id), self.lifetime_secs as i64)
+                .await?;
+        }
+        if attempts > MAX_ATTEMPTS {
+            self.clear(user_id).await?;
+            return Ok(Redemption::TooManyAttempts);
+        }
+        if expected != secret_hash(code.trim()) {
+            return Ok(Redemption::Invalid);
+        }
+
+        // Only the request deleting the code logs in, concurrent ones lose
+        let deleted: i64 = redis.del(code_key(user_id)).await?;
+        if deleted == 0 {
+            return Ok(Redemption::Expired);
+        }
+        self.clear(user_id).await?;
+        Ok(Redemption::Valid)
+    }
+
+    /// Consume a magic link token, returns the user it was issued for unless it expired or was
+    /// used.
+    pub async fn redeem_link(&self, token: &str) -> RedisResult<Option<i32>> {
+        let user_id: Option<i32> = self
+            .redis
+            .clone()
+            .get_del(link_key(&secret_hash(token)))
+            .await?;
+        if let Some(user_id) = user_id {
+            self.clear(user_id).await?;
+        }
+        Ok(user_id)
+    }
+
+    /// Text of the message carrying `challenge`.
+    pub fn message(&self, challenge: &LoginChallenge) -> String {
+        let minutes = self.lifetime_secs / 60;
+        match &self.url {
+            Some(url) => format!(
+                "Your login code is {}, or log in using this link: {url}?token={}. Both are \
+                 valid for {minutes} minutes.",
+                challenge.code, challenge.token
+            ),
+            None => format!(
+                "Your login code is {}, valid for {minutes} minutes.",
+                challenge.code
+            ),
+        }
+    }
+
+    async fn clear(&self, user_id: i32) -> RedisResult<()> {
+        let mut redis = self.redis.clone();
+        let link: Option<String> = redis.get_del(link_user_key(user_id)).await?;
+        let mut keys = vec![code_key(user_id), attempts_key(user_id)];
+        keys.extend(link.map(|hash| link_key(&hash)));
+        redis.del(keys).await
+    }
+}
+
+fn secret_hash(secret: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(secret.as_bytes()))
+}
+
+fn code_key(user_id: i32) -> String {
+    format!("login_code:{user_id}")
+}
+
+fn attempts_key(user_id: i32) -> String {
+    format!("login_code_attempts:{user_id}")
+}
+
+fn link_key(hash: &str) -> String {
+    format!("login_link:{hash}")
+}
+
+fn link_user_key(user_id: i32) -> String {
+    format!("login_link_user:{user_id}")
+}
@@ -0,0 +1,137 @@
+use std::env;
+
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use password_hash::{SaltString, rand_core::OsRng};
+
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// The password is correct but the hash was made with other parameters or without the
+    /// pepper, it should be replaced.
+    NeedsRehash,
+}
+
+/// Argon2 configuration used to hash and verify passwords.
+pub struct PasswordHashing {
+    algorithm: Algorithm,
+    version: Version,
+    params: Params,
+    pepper: Option<Vec<u8>>,
+}
+
+impl PasswordHashing {
+    /// Reads `ARGON2_ALGORITHM` (`argon2id`, `argon2i` or `argon2d`), `ARGON2_VERSION` (`19` or
+    /// `16`), `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS`, `ARGON2_PARALLELISM` and the optional
+    /// `PASSWORD_PEPPER`. Unset values fall back to the Argon2 crate defaults.
+    pub fn from_env() -> Self {
+        let algorithm = env::var("ARGON2_ALGORITHM")
+            .ok()
+            .map(|name| {
+                name.parse()
+                    .unwrap_or_else(|err| panic!("ARGON2_ALGORITHM: {err}"))
+            })
+            .unwrap_or_default();
+        let version = env::var("ARGON2_VERSION")
+            .ok()
+            .map(|version| {
+                version
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|version| Version::try_from(version).ok())
+                    .expect("ARGON2_VERSION must be 19 or 16")
+            })
+            .unwrap_or_default();
+        let params = Params::new(
+            number_from_env("ARGON2_MEMORY_KIB", Params::DEFAULT_M_COST),
+            number_from_env("ARGON2_ITERATIONS", Params::DEFAULT_T_COST),
+            number_from_env("ARGON2_PARALLELISM", Params::DEFAULT_P_COST),
+            None,
+        )
+        .unwrap_or_else(|err| panic!("Argon2 parameters: {err}"));
+        let pepper = env::var("PASSWORD_PEPPER")
+            .ok()
+            .filter(|pepper| !pepper.is_empty())
+            .map(String::into_bytes);
+        let hashing = Self {
+            algorithm,
+            version,
+            params,
+            pepper,
+        };
+        if let Err(err) = hashing.argon2() {
+            panic!("PASSWORD_PEPPER: {err}");
+        }
+        hashing
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, password_hash::Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(self
+            .argon2()?
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string())
+    }
+
+    /// Check `password` against a stored PHC hash string.
+    pub fn verify(&self, password: &str, hash: &str) -> Verification {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return Verification::Invalid; // Invalid stored hash format
+        };
+        let Ok(argon2) = self.argon2() else {
+            return Verification::Invalid;
+        };
+        if argon2.verify_password(password.as_bytes(), &parsed).is_ok() {
+            return match self.is_current(&parsed) {
+                true => Verification::Valid,
+                false => Verification::NeedsRehash,
+            };
+        }
+        // Hashes stored before the pepper was configured
+        if self.pepper.is_some()
+            && Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        {
+            return Verification::NeedsRehash;
+        }
+        Verification::Invalid
+    }
+
+    fn argon2(&self) -> Result<Argon2<'_>, password_hash::Error> {
+        match &self.pepper {
+            Some(pepper) => Ok(Argon2::new_with_secret(
+                pepper,
+                self.algorithm,
+                self.version,
+                self.params.clone(),
+            )?),
+            None => Ok(Argon2::new(
+                self.algorithm,
+                self.version,
+                self.params.clone(),
+            )),
+        }
+    }
+
+    fn is_current(&self, hash: &PasswordHash) -> bool {
+        let params = Params::try_from(hash);
+        Algorithm::try_from(hash.algorithm).is_ok_and(|algorithm| algorithm == self.algorithm)
+            && hash.version == Some(self.version.into())
+            && params.is_ok_and(|params| {
+                params.m_cost() == self.params.m_cost()
+                    && params.t_cost() == self.params.t_cost()
+                    && params.p_cost() == self.params.p_cost()
+            })
+    }
+}
+
+fn number_from_env(name: &str, default: u32) -> u32 {
+    env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{name} must be a positive number"))
+        })
+        .unwrap_or(default)
+}
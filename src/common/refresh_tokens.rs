@@ -0,0 +1,124 @@
+use redis::{
+    AsyncCommands, ExistenceCheck, RedisResult, Script, SetExpiry, SetOptions,
+    aio::MultiplexedConnection,
+};
+
+use crate::common::tokens::random_id;
+
+/// How long a rotated refresh token still gets the token it was rotated to, so parallel requests
+/// presenting the same expired session do not count as reuse.
+const REUSE_GRACE_SECS: u64 = 10;
+
+/// Atomically swaps the current refresh token id of a family.
+///
+/// Returns `1` and the new current id when `ARGV[1]` was the current id, or when it was rotated
+/// to the current id less than `ARGV[4]` seconds ago (`KEYS[2]`). Returns `0` when the family
+/// does not exist and `-1` when an older id was presented, in which case the family is deleted.
+const ROTATE_SCRIPT: &str = r"
+local current = redis.call('GET', KEYS[1])
+if not current then
+    return {0, ''}
+end
+if current == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+    redis.call('SET', KEYS[2], ARGV[2], 'EX', ARGV[4])
+    return {1, ARGV[2]}
+end
+if redis.call('GET', KEYS[2]) == current then
+    return {1, current}
+end
+redis.call('DEL', KEYS[1])
+return {-1, ''}
+";
+
+pub enum Rotation {
+    /// The token was current, or was rotated moments ago; the current refresh token id is
+    /// returned.
+    Rotated(String),
+    /// A rotated token was presented again, the whole family has been revoked.
+    Reused,
+    /// The family expired or was revoked.
+    Unknown,
+}
+
+/// Refresh token families stored in Redis.
+///
+/// Every login starts a family. Only the most recently issued refresh token of a family is
+/// accepted; presenting an older one means it was copied, so the family is revoked. The token
+/// before it is let through for a few seconds after the rotation, as concurrent requests of the
+/// same client refresh with it at once.
+#[derive(Clone)]
+pub struct RefreshFamilies {
+    redis: MultiplexedConnection,
+    lifetime_secs: u64,
+}
+
+impl RefreshFamilies {
+    pub fn new(redis: MultiplexedConnection, lifetime_secs: u64) -> Self {
+        Self {
+            redis,
+            lifetime_secs,
+        }
+    }
+
+    /// Start a new family, returns the family id and the id of its first refresh token.
+    pub async fn start(&self) -> RedisResult<(String, String)> {
+        let (fid, jti) = (random_id(), random_id());
+        self.redis
+            .clone()
+            .set_ex::<_, _, ()>(family_key(&fid), &jti, self.lifetime_secs)
+            .await?;
+        Ok((fid, jti))
+    }
+
+    pub async fn rotate(&self, fid: &str, jti: &str) -> RedisResult<Rotation> {
+        let next = random_id();
+        let (result, current): (i32, String) = Script::new(ROTATE_SCRIPT)
+            .key(family_key(fid))
+            .key(rotated_key(fid, jti))
+            .arg(jti)
+            .arg(&next)
+            .arg(self.lifetime_secs)
+            .arg(REUSE_GRACE_SECS)
+            .invoke_async(&mut self.redis.clone())
+            .await?;
+        Ok(match (result, current) {
+            (1, current) => Rotation::Rotated(current),
+            (0, _) => Rotation::Unknown,
+            _ => Rotation::Reused,
+        })
+    }
+
+    /// Replace the current refresh token id of the family `fid` with a new one, returned unless
+    /// the family expired or was revoked. For when its tokens were revoked by time, but the
+    /// session goes on.
+    pub async fn reissue(&self, fid: &str) -> RedisResult<Option<String>> {
+        let jti = random_id();
+        let replaced: Option<String> = self
+            .redis
+            .clone()
+            .set_options(
+                family_key(fid),
+                &jti,
+                SetOptions::default()
+                    .conditional_set(ExistenceCheck::XX)
+                    .with_expiration(SetExpiry::EX(self.lifetime_secs)),
+            )
+            .await?;
+        Ok(replaced.map(|_| jti))
+    }
+
+    /// Revoke a family, its refresh tokens are no longer accepted.
+    pub async fn revoke(&self, fid: &str) -> RedisResult<()> {
+        self.redis.clone().del(family_key(fid)).await
+    }
+}
+
+fn family_key(fid: &str) -> String {
+    format!("refresh_family:{fid}")
+}
+
+/// Id the refresh token `jti` of the family `fid` was rotated to.
+fn rotated_key(fid: &str, jti: &str) -> String {
+    format!("refresh_family:{fid}:rotated:{jti}")
+}
@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use redis::{AsyncCommands, RedisResult, Script, aio::MultiplexedConnection};
+
+/// Atomically raises the "revoke all before" marker `KEYS[1]` to `ARGV[1]`, a lower time never
+/// replaces a higher one.
+const RAISE_SCRIPT: &str = r"
+local current = tonumber(redis.call('GET', KEYS[1]))
+if current and current >= tonumber(ARGV[1]) then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+return 1
+";
+
+/// Revoked tokens stored in Redis.
+///
+/// Single tokens are revoked by `jti` until they would have expired anyway. Revoking everything
+/// issued to a user before some time stores that time in milliseconds, tokens issued before it
+/// are rejected. A login right after a revocation is not, even within the same second.
+#[derive(Clone)]
+pub struct RevocationList {
+    redis: MultiplexedConnection,
+    /// How long a "revoke all before" marker is kept, the longest lifetime of any token.
+    max_lifetime_secs: u64,
+}
+
+impl RevocationList {
+    pub fn new(redis: MultiplexedConnection, max_lifetime_secs: u64) -> Self {
+        Self {
+            redis,
+            max_lifetime_secs,
+        }
+    }
+
+    /// Revoke the token `jti` until it expires at `expires_at`.
+    pub async fn revoke(&self, jti: &str, expires_at: DateTime<Utc>) -> RedisResult<()> {
+        let remaining = (expires_at - Utc::now()).num_seconds();
+        if remaining <= 0 {
+            return Ok(());
+        }
+        self.redis
+            .clone()
+            .set_ex(revoked_key(jti), 1, remaining as u64)
+            .await
+    }
+
+    /// Revoke every token issued to the user before `before`. An earlier `before` than the
+    /// current one keeps the current one, it must not make revoked tokens valid again.
+    pub async fn revoke_all_before(&self, user_id: i32, before: DateTime<Utc>) -> RedisResult<()> {
+        Script::new(RAISE_SCRIPT)
+            .key(not_before_key(user_id))
+            .arg(before.timestamp_millis())
+            .arg(self.max_lifetime_secs)
+            .invoke_async::<i32>(&mut self.redis.clone())
+            .await
+            .map(drop)
+    }
+
+    pub async fn is_revoked(
+        &self,
+        jti: &str,
+        user_id: i32,
+        issued_at: Option<DateTime<Utc>>,
+    ) -> RedisResult<bool> {
+        let (revoked, not_before): (Option<i32>, Option<i64>) = redis::pipe()
+            .get(revoked_key(jti))
+            .get(not_before_key(user_id))
+            .query_async(&mut self.redis.clone())
+            .await?;
+
+        let issued_before = match (not_before, issued_at) {
+            (Some(not_before), Some(issued_at)) => issued_at.timestamp_millis() < not_before,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        Ok(revoked.is_some() || issued_before)
+    }
+}
+
+fn revoked_key(jti: &str) -> String {
+    format!("revoked_token:{jti}")
+}
+
+fn not_before_key(user_id: i32) -> String {
+    format!("tokens_not_before_ms:{user_id}")
+}
@@ -0,0 +1,74 @@
+use api::{models::session::NewSession, schema::user_sessions};
+use chrono::{DateTime, Utc};
+use diesel::{dsl::now, prelude::*};
+
+/// Record the session started with the refresh token family `fid`.
+pub fn record(
+    conn: &mut PgConnection,
+    user_id: i32,
+    fid: &str,
+    user_agent: Option<&str>,
+    ip_address: Option<String>,
+) -> QueryResult<()> {
+    diesel::insert_into(user_sessions::table)
+        .values(NewSession {
+            user_id,
+            family_id: fid,
+            user_agent,
+            ip_address,
+        })
+        .execute(conn)
+        .map(drop)
+}
+
+/// Note that the session of the family `fid` was just used to refresh its tokens.
+pub fn touch(conn: &mut PgConnection, fid: &str) -> QueryResult<()> {
+    diesel::update(user_sessions::table.filter(user_sessions::family_id.eq(fid)))
+        .set(user_sessions::last_seen_at.eq(now))
+        .execute(conn)
+        .map(drop)
+}
+
+/// Mark the session of the family `fid` as revoked.
+pub fn revoke(conn: &mut PgConnection, fid: &str) -> QueryResult<()> {
+    diesel::update(
+        user_sessions::table
+            .filter(user_sessions::family_id.eq(fid))
+            .filter(user_sessions::revoked_at.is_null()),
+    )
+    .set(user_sessions::revoked_at.eq(now.nullable()))
+    .execute(conn)
+    .map(drop)
+}
+
+/// Mark the user's sessions last refreshed before `before` as revoked, the ones a
+/// `RevocationList::revoke_all_before` with the same time ends.
+pub fn revoke_all_before(
+    conn: &mut PgConnection,
+    user_id: i32,
+    before: DateTime<Utc>,
+) -> QueryResult<()> {
+    diesel::update(
+        user_sessions::table
+            .filter(user_sessions::user_id.eq(user_id))
+            .filter(user_sessions::last_seen_at.lt(before.naive_utc()))
+            .filter(user_sessions::revoked_at.is_null()),
+    )
+    .set(user_sessions::revoked_at.eq(now.nullable()))
+    .execute(conn)
+    .map(drop)
+}
+
+/// Mark the user's sessions other than the one of the family `fid` as revoked, returns their
+/// families.
+pub fn revoke_others(conn: &mut PgConnection, user_id: i32, fid: &str) -> QueryResult<Vec<String>> {
+    diesel::update(
+        user_sessions::table
+            .filter(user_sessions::user_id.eq(user_id))
+            .filter(user_sessions::family_id.ne(fid))
+            .filter(user_sessions::revoked_at.is_null()),
+    )
+    .set(user_sessions::revoked_at.eq(now.nullable()))
+    .returning(user_sessions::family_id)
+    .get_results(conn)
+}
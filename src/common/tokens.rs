@@ -0,0 +1,198 @@
+use std::{env, fmt};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, TimeDelta};
+use jwt_compact::{
+    AlgorithmExt, Claims, CreationError, Header, ParseError, TimeOptions, UntrustedToken,
+    ValidationError, alg::Ed25519,
+};
+use password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::common::keys::Keyring;
+
+/// `typ` header of access tokens, see RFC 9068.
+const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+/// `typ` header of refresh tokens, keeps them from being accepted as access tokens.
+const REFRESH_TOKEN_TYPE: &str = "refresh+jwt";
+/// `typ` header of tokens proving the password step of a login that still needs a second factor.
+const MFA_TOKEN_TYPE: &str = "mfa+jwt";
+
+/// Custom claims as signed, with the issue time in milliseconds. The standard `iat` claim only
+/// has whole seconds, too coarse to tell whether a token was issued before or after a revocation
+/// in the same second.
+#[derive(Serialize, Deserialize)]
+struct Signed<C> {
+    #[serde(flatten)]
+    custom: C,
+    #[serde(rename = "iat_ms")]
+    issued_at_ms: i64,
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    Creation(CreationError),
+    Parse(ParseError),
+    Validation(ValidationError),
+    UnknownKey,
+    WrongType,
+}
+
+impl TokenError {
+    pub fn is_expired(&self) -> bool {
+        matches!(self, TokenError::Validation(ValidationError::Expired))
+    }
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Creation(err) => write!(f, "failed to create token: {err}"),
+            TokenError::Parse(err) => write!(f, "malformed token: {err}"),
+            TokenError::Validation(err) => write!(f, "invalid token: {err}"),
+            TokenError::UnknownKey => write!(f, "token signed with an unknown key"),
+            TokenError::WrongType => write!(f, "unexpected token type"),
+        }
+    }
+}
+
+/// Signs and verifies access, refresh and MFA pending tokens with the [`Keyring`].
+pub struct TokenIssuer {
+    keyring: Keyring,
+    access_lifetime: TimeDelta,
+    refresh_lifetime: TimeDelta,
+    mfa_lifetime: TimeDelta,
+    time_options: TimeOptions,
+}
+
+impl TokenIssuer {
+    /// Lifetimes are read from `ACCESS_TOKEN_LIFETIME`, `REFRESH_TOKEN_LIFETIME` and
+    /// `MFA_TOKEN_LIFETIME` (seconds).
+    pub fn from_env(keyring: Keyring) -> Self {
+        Self {
+            keyring,
+            access_lifetime: lifetime_from_env("ACCESS_TOKEN_LIFETIME", 60),
+            refresh_lifetime: lifetime_from_env("REFRESH_TOKEN_LIFETIME", 30 * 60),
+            mfa_lifetime: lifetime_from_env("MFA_TOKEN_LIFETIME", 5 * 60),
+            time_options: TimeOptions::default(),
+        }
+    }
+
+    pub fn access_lifetime(&self) -> TimeDelta {
+        self.access_lifetime
+    }
+
+    pub fn refresh_lifetime(&self) -> TimeDelta {
+        self.refresh_lifetime
+    }
+
+    pub fn mfa_lifetime(&self) -> TimeDelta {
+        self.mfa_lifetime
+    }
+
+    pub fn create_access_token<C: Serialize>(&self, claims: &C) -> Result<String, TokenError> {
+        self.sign(ACCESS_TOKEN_TYPE, claims, self.access_lifetime)
+    }
+
+    pub fn create_refresh_token<C: Serialize>(&self, claims: &C) -> Result<String, TokenError> {
+        self.sign(REFRESH_TOKEN_TYPE, claims, self.refresh_lifetime)
+    }
+
+    pub fn verify_access_token<C: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<Claims<C>, TokenError> {
+        self.verify(ACCESS_TOKEN_TYPE, token)
+    }
+
+    pub fn verify_refresh_token<C: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<Claims<C>, TokenError> {
+        self.verify(REFRESH_TOKEN_TYPE, token)
+    }
+
+    pub fn create_mfa_token<C: Serialize>(&self, claims: &C) -> Result<String, TokenError> {
+        self.sign(MFA_TOKEN_TYPE, claims, self.mfa_lifetime)
+    }
+
+    pub fn verify_mfa_token<C: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<Claims<C>, TokenError> {
+        self.verify(MFA_TOKEN_TYPE, token)
+    }
+
+    fn sign<C: Serialize>(
+        &self,
+        token_type: &str,
+        claims: &C,
+        lifetime: TimeDelta,
+    ) -> Result<String, TokenError> {
+        let header = Header::empty()
+            .with_key_id(self.keyring.kid())
+            .with_token_type(token_type);
+        let issued_at = (self.time_options.clock_fn)();
+        let mut claims = Claims::new(Signed {
+            custom: claims,
+            issued_at_ms: issued_at.timestamp_millis(),
+        });
+        claims.issued_at = Some(issued_at);
+        claims.expiration = Some(issued_at + lifetime);
+        Ed25519
+            .token(&header, &claims, self.keyring.signing_key())
+            .map_err(TokenError::Creation)
+    }
+
+    fn verify<C: DeserializeOwned>(
+        &self,
+        token_type: &str,
+        token: &str,
+    ) -> Result<Claims<C>, TokenError> {
+        let untrusted = UntrustedToken::new(token).map_err(TokenError::Parse)?;
+        if untrusted.header().token_type.as_deref() != Some(token_type) {
+            return Err(TokenError::WrongType);
+        }
+        let key = untrusted
+            .header()
+            .key_id
+            .as_deref()
+            .and_then(|kid| self.keyring.verifying_key(kid))
+            .ok_or(TokenError::UnknownKey)?;
+
+        let token = Ed25519
+            .validator::<Signed<C>>(key)
+            .validate(&untrusted)
+            .map_err(TokenError::Validation)?;
+        token
+            .claims()
+            .validate_expiration(&self.time_options)
+            .map_err(TokenError::Validation)?;
+
+        let (_, signed) = token.into_parts();
+        let mut claims = Claims::new(signed.custom.custom);
+        claims.expiration = signed.expiration;
+        claims.not_before = signed.not_before;
+        claims.issued_at = DateTime::from_timestamp_millis(signed.custom.issued_at_ms);
+        Ok(claims)
+    }
+}
+
+/// Random URL safe identifier, used for `jti` and token family ids.
+pub fn random_id() -> String {
+    let mut bytes = [0_u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn lifetime_from_env(name: &str, default_secs: i64) -> TimeDelta {
+    let secs = env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{name} must be a number of seconds"))
+        })
+        .unwrap_or(default_secs);
+    TimeDelta::seconds(secs)
+}
@@ -0,0 +1,144 @@
+use std::env;
+
+use api::models::user::VerificationChannel;
+use redis::{AsyncCommands, RedisResult, aio::MultiplexedConnection};
+
+/// How long an OTP is valid, the notification worker stores it for as long.
+const OTP_TTL_SECS: u64 = 300;
+/// Wrong codes accepted before the OTP is discarded.
+const MAX_ATTEMPTS: i64 = 5;
+
+pub enum Confirmation {
+    Verified(VerificationChannel),
+    Invalid,
+    Expired,
+    TooManyAttempts,
+}
+
+/// Verification codes of users.
+///
+/// The notification worker generates the code and stores it under `otp:{user_id}`, this only
+/// remembers which channel it was sent to and checks submitted codes against it.
+#[derive(Clone)]
+pub struct OtpVerifier {
+    redis: MultiplexedConnection,
+}
+
+impl OtpVerifier {
+    pub fn new(redis: MultiplexedConnection) -> Self {
+        Self { redis }
+    }
+
+    /// Record that a code for `channel` is about to be sent to the user.
+    pub async fn begin(&self, user_id: i32, channel: VerificationChannel) -> RedisResult<()> {
+        redis::pipe()
+            .del(otp_key(user_id))
+            .del(attempts_key(user_id))
+            .set_ex(channel_key(user_id), channel_name(channel), OTP_TTL_SECS)
+            .query_async::<()>(&mut self.redis.clone())
+            .await
+    }
+
+    /// Check a submitted code, a correct code can only be used once.
+    pub async fn confirm(&self, user_id: i32, code: &str) -> RedisResult<Confirmation> {
+        let mut redis = self.redis.clone();
+        let (otp, channel): (Option<String>, Option<String>) = redis::pipe()
+            .get(otp_key(user_id))
+            .get(channel_key(user_id))
+            .query_async(&mut redis)
+            .await?;
+        let (Some(otp), Some(channel)) = (otp, channel) else {
+            return Ok(Confirmation::Expired);
+        };
+
+        let attempts: i64 = redis.incr(attempts_key(user_id), 1).await?;
+        if attempts == 1 {
+            redis
+                .expire::<_, ()>(attempts_key(user_id), OTP_TTL_SECS as i64)
+                .await?;
+        }
+        if attempts > MAX_ATTEMPTS {
+            self.clear(user_id).await?;
+            return Ok(Confirmation::TooManyAttempts);
+        }
+        if otp != code.trim() {
+            return Ok(Confirmation::Invalid);
+        }
+
+        self.clear(user_id).await?;
+        Ok(match channel.as_str() {
+            "phone" => Confirmation::Verified(VerificationChannel::Phone),
+            _ => Confirmation::Verified(VerificationChannel::Email),
+        })
+    }
+
+    async fn clear(&self, user_id: i32) -> RedisResult<()> {
+        self.redis
+            .clone()
+            .del(&[
+                otp_key(user_id),
+                channel_key(user_id),
+                attempts_key(user_id),
+            ])
+            .await
+    }
+}
+
+/// Which verified contact details `login` requires, from `LOGIN_REQUIRES_VERIFICATION`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginVerification {
+    None,
+    Email,
+    Phone,
+    Any,
+}
+
+impl LoginVerification {
+    pub fn from_env() -> Self {
+        match env::var("LOGIN_REQUIRES_VERIFICATION").as_deref() {
+            Err(_) | Ok("") | Ok("none") => LoginVerification::None,
+            Ok("email") => LoginVerification::Email,
+            Ok("phone") => LoginVerification::Phone,
+            Ok("any") => LoginVerification::Any,
+            Ok(other) => panic!(
+                "LOGIN_REQUIRES_VERIFICATION must be one of none, email, phone or any, got {other}"
+            ),
+        }
+    }
+
+    /// Error message when a user with the given verification state may not log in.
+    pub fn check(self, email_verified: bool, phone_verified: bool) -> Result<(), &'static str> {
+        match self {
+            LoginVerification::None => Ok(()),
+            LoginVerification::Email if !email_verified => {
+                Err("Please verify your email address before logging in")
+            }
+            LoginVerification::Phone if !phone_verified => {
+                Err("Please verify your phone number before logging in")
+            }
+            LoginVerification::Any if !email_verified && !phone_verified => {
+                Err("Please verify your email address or phone number before logging in")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub fn channel_name(channel: VerificationChannel) -> &'static str {
+    match channel {
+        VerificationChannel::Email => "email",
+        VerificationChannel::Phone => "phone",
+    }
+}
+
+fn otp_key(user_id: i32) -> String {
+    format!("otp:{user_id}")
+}
+
+fn channel_key(user_id: i32) -> String {
+    format!("otp_channel:{user_id}")
+}
+
+fn attempts_key(user_id: i32) -> String {
+    format!("otp_attempts:{user_id}")
+}
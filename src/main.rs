@@ -1,30 +1,61 @@
 use std::env;
 
-use actix::api::health_check_api::configure_health_check_api;
+use actix::api::{health_check_api::configure_health_check_api, jwks_api::configure_jwks_api};
 use actix_cors::Cors;
 use actix_governor::{Governor, GovernorConfigBuilder};
-use actix_jwt_auth_middleware::{Authority, TokenSigner, use_jwt::UseJWTOnApp};
 use actix_web::{App, HttpRequest, HttpResponse, HttpServer, error, middleware::Logger, web};
-use api::models::user::UserJWT;
 use collection::{
     connections::connections::{create_amqp_channel, create_redis_conn},
-    operations::validation,
+    operations::{password_policy::PasswordPolicy, phone::PhoneNumbers, validation},
 };
 use diesel::{
     PgConnection,
     r2d2::{self, ConnectionManager},
 };
-use ed25519_compact::{KeyPair, Seed};
-use jwt_compact::alg::Ed25519;
 use serde_json::json;
 mod actix;
+mod admin;
+mod common;
 
-use crate::actix::api::{auth_api::config_auth_api, users_api::configure_users_api};
+use crate::{
+    actix::{
+        api::{
+            account_api::configure_account_api, api_keys_api::configure_api_keys_api,
+            audit_api::configure_audit_api, auth_api::config_auth_api, mfa_api::configure_mfa_api,
+            oidc_api::configure_oidc_api, partner_api::configure_partner_api,
+            users_api::configure_users_api,
+        },
+        auth::{CookieSettings, JwtAuth},
+        csrf::Csrf,
+    },
+    common::{
+        contact_changes::ContactChanges,
+        deletion_codes::DeletionCodes,
+        erasure::AccountErasure,
+        keys::Keyring,
+        login_throttle::LoginThrottle,
+        mfa::Mfa,
+        notifications::Notifier,
+        oidc::Oidc,
+        password_reset::PasswordResets,
+        passwordless::PasswordlessLogins,
+        passwords::PasswordHashing,
+        refresh_tokens::RefreshFamilies,
+        revocation::RevocationList,
+        tokens::TokenIssuer,
+        verification::{LoginVerification, OtpVerifier},
+    },
+};
 
 type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        return admin::run(&args);
+    }
+
     if env::var_os("RUST_LOG").is_none() {
         unsafe {
             env::set_var("RUST_LOG", "actix_web=info");
@@ -32,12 +63,42 @@ async fn main() -> std::io::Result<()> {
     }
     dotenvy::dotenv().ok();
     env_logger::init();
-    create_redis_conn().await;
-    create_amqp_channel().await;
+    let redis_conn = create_redis_conn().await;
+    let notifier = Notifier::new(create_amqp_channel().await)
+        .await
+        .expect("Failed to declare notifications queue");
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let manager = r2d2::ConnectionManager::<PgConnection>::new(database_url);
     let pool = r2d2::Pool::builder().build(manager).unwrap();
-    let key_pair = KeyPair::from_seed(Seed::default());
+    let keyring = Keyring::from_env().unwrap_or_else(|err| panic!("Signing keys: {err}"));
+    log::info!("Signing tokens with key {}", keyring.kid());
+    let keys = web::Data::new(keyring.clone());
+    let tokens = web::Data::new(TokenIssuer::from_env(keyring));
+    let refresh_lifetime_secs = tokens.refresh_lifetime().num_seconds() as u64;
+    let families = web::Data::new(RefreshFamilies::new(
+        redis_conn.clone(),
+        refresh_lifetime_secs,
+    ));
+    let revocations = web::Data::new(RevocationList::new(
+        redis_conn.clone(),
+        refresh_lifetime_secs,
+    ));
+    let verifier = web::Data::new(OtpVerifier::new(redis_conn.clone()));
+    let contact_changes = web::Data::new(ContactChanges::new(redis_conn.clone()));
+    let deletion_codes = web::Data::new(DeletionCodes::new(redis_conn.clone()));
+    let resets = web::Data::new(PasswordResets::from_env(redis_conn.clone()));
+    let passwordless = web::Data::new(PasswordlessLogins::from_env(redis_conn.clone()));
+    let throttle = web::Data::new(LoginThrottle::from_env(redis_conn.clone()));
+    let oidc = web::Data::new(Oidc::from_env(redis_conn));
+    let notifier = web::Data::new(notifier);
+    let login_verification = web::Data::new(LoginVerification::from_env());
+    let passwords = web::Data::new(PasswordHashing::from_env());
+    let password_policy = web::Data::new(PasswordPolicy::from_env());
+    let phones = web::Data::new(PhoneNumbers::from_env());
+    let mfa = web::Data::new(Mfa::from_env());
+    let erasure = web::Data::new(AccountErasure::from_env());
+    let cookies = web::Data::new(CookieSettings::from_env());
+    let pool = web::Data::new(pool);
     HttpServer::new(move || {
         let governor_conf = GovernorConfigBuilder::default()
             .requests_per_second(1) // 1 request per second
@@ -45,19 +106,6 @@ async fn main() -> std::io::Result<()> {
             .finish()
             .unwrap();
         let governor = Governor::new(&governor_conf);
-        let signer = TokenSigner::<UserJWT, Ed25519>::new()
-            .signing_key(key_pair.sk.clone())
-            .algorithm(Ed25519)
-            .build()
-            .expect("token signer build failed");
-
-        let authority = Authority::new()
-            .refresh_authorizer(|| async move { Ok(()) })
-            .token_signer(Some(signer))
-            .verifying_key(key_pair.sk.public_key())
-            .build()
-            .expect("authority build failed");
-
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -68,18 +116,65 @@ async fn main() -> std::io::Result<()> {
         let validate_query_config = actix_web_validator::QueryConfig::default()
             .error_handler(|err, rec| validation_error_handler("query", err, rec));
         App::new()
-            .app_data(web::Data::new(pool.clone()))
+            .app_data(pool.clone())
+            .app_data(keys.clone())
+            .app_data(tokens.clone())
+            .app_data(families.clone())
+            .app_data(revocations.clone())
+            .app_data(verifier.clone())
+            .app_data(resets.clone())
+            .app_data(contact_changes.clone())
+            .app_data(deletion_codes.clone())
+            .app_data(passwordless.clone())
+            .app_data(throttle.clone())
+            .app_data(notifier.clone())
+            .app_data(login_verification.clone())
+            .app_data(passwords.clone())
+            .app_data(password_policy.clone())
+            .app_data(phones.clone())
+            .app_data(mfa.clone())
+            .app_data(erasure.clone())
+            .app_data(oidc.clone())
+            .app_data(cookies.clone())
             .app_data(validate_path_config)
             .app_data(validate_query_config)
             .wrap(governor)
             .wrap(cors)
             .wrap(Logger::default())
             .configure(configure_health_check_api)
+            .configure(configure_jwks_api)
             .configure(config_auth_api)
-            .use_jwt(
-                authority,
-                web::scope("/users").configure(configure_users_api),
+            .configure(configure_oidc_api)
+            .service(
+                web::scope("/users")
+                    .wrap(JwtAuth::new(
+                        pool.clone(),
+                        tokens.clone(),
+                        families.clone(),
+                        revocations.clone(),
+                        cookies.clone(),
+                    ))
+                    // Wrapped last to run first, a forged request must not rotate the session
+                    .wrap(Csrf::new(cookies.clone()))
+                    .configure(configure_account_api)
+                    .configure(configure_mfa_api)
+                    .configure(configure_api_keys_api)
+                    .configure(configure_users_api),
+            )
+            .service(
+                web::scope("/admin")
+                    .wrap(JwtAuth::new(
+                        pool.clone(),
+                        tokens.clone(),
+                        families.clone(),
+                        revocations.clone(),
+                        cookies.clone(),
+                    ))
+                    .wrap(Csrf::new(cookies.clone()))
+                    .configure(configure_audit_api),
             )
+            // Partner integrations authenticate every route with an `X-Api-Key`
+            .service(web::scope("/partner").configure(configure_partner_api))
     })
     .bind("127.0.0.1:3035")?
     .run()